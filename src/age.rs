@@ -0,0 +1,47 @@
+//! Coloring by modification age, so stale data stands out.
+
+use eframe::egui::Color32;
+use std::time::SystemTime;
+
+/// Color of something modified just now
+const FRESH: Color32 = Color32::from_rgb(60, 200, 110);
+/// Color of something untouched for `STALE_DAYS` or longer
+const STALE: Color32 = Color32::from_rgb(140, 90, 50);
+const STALE_DAYS: f32 = 5. * 365.;
+
+/// How stale something is, from 0 (fresh) to 1. Logarithmic, so recent changes are distinguishable.
+pub fn staleness(modified: SystemTime) -> f32 {
+    let days = modified
+        .elapsed()
+        .map(|e| e.as_secs_f32() / 86400.)
+        .unwrap_or_default();
+    ((1. + days).ln() / (1. + STALE_DAYS).ln()).min(1.)
+}
+
+pub fn age_color(modified: SystemTime) -> Color32 {
+    let t = staleness(modified);
+    let lerp = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t) as u8;
+    Color32::from_rgb(
+        lerp(FRESH.r(), STALE.r()),
+        lerp(FRESH.g(), STALE.g()),
+        lerp(FRESH.b(), STALE.b()),
+    )
+}
+
+/// How size bars are colored
+#[derive(Debug, Clone, Copy)]
+pub struct BarStyle {
+    pub accent: Color32,
+    /// Tint bars by the modification time of what they represent
+    pub by_age: bool,
+}
+
+impl BarStyle {
+    pub fn color(&self, modified: SystemTime) -> Color32 {
+        if self.by_age {
+            age_color(modified)
+        } else {
+            self.accent
+        }
+    }
+}
@@ -0,0 +1,466 @@
+//! Reading the contents of archives into a DirInfo, so they can be browsed like a directory.
+//! Entries get virtual paths below the archive path, e.g. `backup.zip/docs/a.txt`.
+
+use crate::timeline::days_from_civil;
+use diskspace_insight::{DirInfo, Directory, File, FileType};
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::hash::Hasher;
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, SystemTime};
+use twox_hash::XxHash64;
+
+const TAR_BLOCK: usize = 512;
+/// The largest GNU long name or pax header that is read. Real ones are a few hundred bytes.
+const MAX_HEADER_RECORD: u64 = 64 * 1024;
+/// File extensions worth checking for archives during a directory scan
+const ARCHIVE_EXTENSIONS: &[&str] = &[
+    "zip", "jar", "war", "ear", "apk", "tar", "gz", "tgz", "zst", "tzst",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArchiveFormat {
+    Zip,
+    Tar,
+    TarGz,
+    TarZst,
+    SevenZip,
+}
+
+impl ArchiveFormat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Zip => "zip",
+            ArchiveFormat::Tar => "tar",
+            ArchiveFormat::TarGz => "tar.gz",
+            ArchiveFormat::TarZst => "tar.zst",
+            ArchiveFormat::SevenZip => "7z",
+        }
+    }
+
+    /// Whether the contents can be listed
+    pub fn is_supported(&self) -> bool {
+        *self != ArchiveFormat::SevenZip
+    }
+}
+
+fn is_tar_header(block: &[u8]) -> bool {
+    block.len() >= 263 && &block[257..262] == b"ustar"
+}
+
+/// The first bytes of a stream, or fewer if it is shorter
+fn read_start(reader: impl Read, len: usize) -> Vec<u8> {
+    let mut start = vec![];
+    let _ = reader.take(len as u64).read_to_end(&mut start);
+    start
+}
+
+/// Find out the archive format from the magic bytes at the start of a file
+pub fn detect(path: &Path) -> Option<ArchiveFormat> {
+    let start = read_start(fs::File::open(path).ok()?, TAR_BLOCK);
+    if start.starts_with(b"PK\x03\x04") || start.starts_with(b"PK\x05\x06") {
+        Some(ArchiveFormat::Zip)
+    } else if start.starts_with(&[0x37, 0x7a, 0xbc, 0xaf, 0x27, 0x1c]) {
+        Some(ArchiveFormat::SevenZip)
+    } else if is_tar_header(&start) {
+        Some(ArchiveFormat::Tar)
+    } else if start.starts_with(&[0x1f, 0x8b]) {
+        // A gzip file is only an archive if there is a tarball inside
+        let decoder = flate2::read::GzDecoder::new(fs::File::open(path).ok()?);
+        Some(ArchiveFormat::TarGz).filter(|_| is_tar_header(&read_start(decoder, TAR_BLOCK)))
+    } else if start.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+        let decoder = zstd::Decoder::new(fs::File::open(path).ok()?).ok()?;
+        Some(ArchiveFormat::TarZst).filter(|_| is_tar_header(&read_start(decoder, TAR_BLOCK)))
+    } else {
+        None
+    }
+}
+
+/// What an archive holds beyond the DirInfo of its contents
+#[derive(Debug, Clone)]
+pub struct ArchiveInfo {
+    pub format: ArchiveFormat,
+    /// Size of the archive file itself
+    pub archive_size: u64,
+    /// Compressed size of each file, by virtual path.
+    /// For compressed tarballs this is estimated from the position in the compressed stream.
+    pub compressed: HashMap<PathBuf, u64>,
+}
+
+/// Uncompressed size divided by compressed size, if there is anything to compare
+pub fn ratio(uncompressed: u64, compressed: u64) -> Option<f32> {
+    if compressed == 0 {
+        None
+    } else {
+        Some(uncompressed as f32 / compressed as f32)
+    }
+}
+
+/// One entry of an archive
+#[derive(Debug, Clone)]
+struct Entry {
+    name: String,
+    is_dir: bool,
+    size: u64,
+    compressed: u64,
+    modified: SystemTime,
+    hash: u64,
+}
+
+/// Read the contents of an archive
+pub fn scan_archive(path: &Path) -> io::Result<(DirInfo, ArchiveInfo)> {
+    let format = detect(path)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Not a supported archive"))?;
+    let file = fs::File::open(path)?;
+    let archive_size = file.metadata()?.len();
+
+    let entries = match format {
+        ArchiveFormat::Zip => read_zip(file)?,
+        ArchiveFormat::Tar => read_counted(file, Ok)?,
+        ArchiveFormat::TarGz => read_counted(file, |r| Ok(flate2::read::GzDecoder::new(r)))?,
+        ArchiveFormat::TarZst => read_counted(file, zstd::Decoder::new)?,
+        ArchiveFormat::SevenZip => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Listing 7z archives is not supported",
+            ))
+        }
+    };
+
+    let (info, compressed) = build_info(path, &entries);
+    Ok((
+        info,
+        ArchiveInfo {
+            format,
+            archive_size,
+            compressed,
+        },
+    ))
+}
+
+fn zip_time(time: zip::DateTime) -> SystemTime {
+    let days = days_from_civil(time.year() as i64, time.month() as u32, time.day() as u32);
+    let secs =
+        days * 86400 + time.hour() as i64 * 3600 + time.minute() as i64 * 60 + time.second() as i64;
+    SystemTime::UNIX_EPOCH + Duration::from_secs(secs.max(0) as u64)
+}
+
+fn read_zip(file: fs::File) -> io::Result<Vec<Entry>> {
+    let mut archive = zip::ZipArchive::new(file)?;
+    let mut entries = vec![];
+    for i in 0..archive.len() {
+        let entry = archive.by_index_raw(i)?;
+        entries.push(Entry {
+            name: entry.name().to_string(),
+            is_dir: entry.is_dir(),
+            size: entry.size(),
+            compressed: entry.compressed_size(),
+            modified: zip_time(entry.last_modified()),
+            hash: entry.crc32() as u64,
+        });
+    }
+    Ok(entries)
+}
+
+/// Counts the bytes read from the archive file, below any decompression
+struct CountingReader<R> {
+    inner: R,
+    count: Rc<Cell<u64>>,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.count.set(self.count.get() + read as u64);
+        Ok(read)
+    }
+}
+
+/// Read a tarball through a decompressor, keeping track of the compressed position
+fn read_counted<D: Read>(
+    file: fs::File,
+    decompress: impl FnOnce(io::BufReader<CountingReader<fs::File>>) -> io::Result<D>,
+) -> io::Result<Vec<Entry>> {
+    let count = Rc::new(Cell::new(0));
+    let counting = CountingReader {
+        inner: file,
+        count: count.clone(),
+    };
+    let reader = decompress(io::BufReader::new(counting))?;
+    read_tar(reader, || count.get())
+}
+
+/// An octal number field, or a base-256 one for large values
+fn tar_number(field: &[u8]) -> u64 {
+    if field.first().map(|b| b & 0x80 != 0).unwrap_or_default() {
+        return field[1..]
+            .iter()
+            .fold((field[0] & 0x7f) as u64, |n, b| (n << 8) | *b as u64);
+    }
+    let text: String = field
+        .iter()
+        .take_while(|b| **b != 0)
+        .map(|b| *b as char)
+        .collect();
+    u64::from_str_radix(text.trim(), 8).unwrap_or_default()
+}
+
+fn tar_string(field: &[u8]) -> String {
+    let end = field.iter().position(|b| *b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).to_string()
+}
+
+/// `path` and `size` from the records of a pax extended header
+fn pax_records(data: &[u8]) -> (Option<String>, Option<u64>) {
+    let mut path = None;
+    let mut size = None;
+    for record in String::from_utf8_lossy(data).lines() {
+        // Records look like "30 path=some/long/name"
+        let record = record.split_once(' ').map(|r| r.1).unwrap_or_default();
+        if let Some(value) = record.strip_prefix("path=") {
+            path = Some(value.to_string());
+        } else if let Some(value) = record.strip_prefix("size=") {
+            size = value.parse().ok();
+        }
+    }
+    (path, size)
+}
+
+/// Read the entries of a tar stream. `position` tells how far into the
+/// (possibly compressed) file the reader is, to attribute compressed bytes to entries.
+fn read_tar(mut reader: impl Read, position: impl Fn() -> u64) -> io::Result<Vec<Entry>> {
+    let mut entries = vec![];
+    let mut header = [0u8; TAR_BLOCK];
+    let mut long_name = None;
+    let mut pax_size = None;
+    loop {
+        let start = position();
+        if reader.read_exact(&mut header).is_err() || header.iter().all(|b| *b == 0) {
+            break;
+        }
+        let typeflag = header[156];
+        let size = pax_size
+            .take()
+            .unwrap_or_else(|| tar_number(&header[124..136]));
+        let padded = size.div_ceil(TAR_BLOCK as u64) * TAR_BLOCK as u64;
+
+        match typeflag {
+            // GNU long name and pax headers describe the next entry
+            b'L' | b'x' => {
+                if size > MAX_HEADER_RECORD {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("{} byte extended header", size),
+                    ));
+                }
+                let mut data = vec![];
+                (&mut reader).take(padded).read_to_end(&mut data)?;
+                data.truncate(size as usize);
+                if typeflag == b'L' {
+                    long_name = Some(tar_string(&data));
+                } else {
+                    let (path, size) = pax_records(&data);
+                    long_name = path.or(long_name);
+                    pax_size = size;
+                }
+                continue;
+            }
+            b'0' | 0 | b'5' | b'7' => {}
+            // Links, devices and global headers have no content of their own
+            _ => {
+                io::copy(&mut (&mut reader).take(padded), &mut io::sink())?;
+                long_name = None;
+                continue;
+            }
+        }
+
+        let mut name = long_name.take().unwrap_or_else(|| {
+            let name = tar_string(&header[0..100]);
+            let prefix = tar_string(&header[345..500]);
+            if is_tar_header(&header) && !prefix.is_empty() {
+                format!("{}/{}", prefix, name)
+            } else {
+                name
+            }
+        });
+        let is_dir = typeflag == b'5' || name.ends_with('/');
+        if is_dir {
+            name = name.trim_end_matches('/').to_string();
+        }
+
+        let mut hasher = XxHash64::default();
+        let mut data = (&mut reader).take(size);
+        let mut buf = vec![0u8; 64 * 1024];
+        loop {
+            let read = data.read(&mut buf)?;
+            if read == 0 {
+                break;
+            }
+            hasher.write(&buf[..read]);
+        }
+        io::copy(&mut (&mut reader).take(padded - size), &mut io::sink())?;
+
+        entries.push(Entry {
+            name,
+            is_dir,
+            size,
+            compressed: position() - start,
+            modified: SystemTime::UNIX_EPOCH + Duration::from_secs(tar_number(&header[136..148])),
+            hash: hasher.finish(),
+        });
+    }
+    Ok(entries)
+}
+
+/// The virtual path of an entry. `..` and absolute prefixes are dropped.
+fn entry_path(root: &Path, name: &str) -> Option<PathBuf> {
+    let relative: PathBuf = Path::new(name)
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(n) => Some(n),
+            _ => None,
+        })
+        .collect();
+    if relative.as_os_str().is_empty() {
+        None
+    } else {
+        Some(root.join(relative))
+    }
+}
+
+/// Add a directory and its missing ancestors below `root` to the tree
+fn ensure_dir(info: &mut DirInfo, root: &Path, dir: &Path) {
+    if info.tree.contains_key(dir) || !dir.starts_with(root) {
+        return;
+    }
+    let parent = dir.parent().map(|p| p.to_path_buf());
+    if let Some(parent) = &parent {
+        ensure_dir(info, root, parent);
+        if let Some(parent_dir) = info.tree.get_mut(parent) {
+            parent_dir.directories.push(dir.to_path_buf());
+        }
+    }
+    info.tree.insert(
+        dir.to_path_buf(),
+        Directory {
+            path: dir.to_path_buf(),
+            parent,
+            ..Default::default()
+        },
+    );
+}
+
+fn build_info(root: &Path, entries: &[Entry]) -> (DirInfo, HashMap<PathBuf, u64>) {
+    let mut info = DirInfo::new();
+    let mut compressed = HashMap::new();
+    info.tree.insert(
+        root.to_path_buf(),
+        Directory {
+            path: root.to_path_buf(),
+            parent: root.parent().map(|p| p.to_path_buf()),
+            ..Default::default()
+        },
+    );
+
+    for entry in entries {
+        let path = match entry_path(root, &entry.name) {
+            Some(path) => path,
+            None => continue,
+        };
+        if entry.is_dir {
+            ensure_dir(&mut info, root, &path);
+            continue;
+        }
+        let parent = path.parent().unwrap_or(root).to_path_buf();
+        ensure_dir(&mut info, root, &parent);
+
+        let file = File {
+            size: entry.size,
+            ext: path
+                .extension()
+                .map(|e| e.to_string_lossy().to_string().to_lowercase()),
+            path: path.clone(),
+            modified: entry.modified,
+            hash: entry.hash,
+        };
+        if let Some(dir) = info.tree.get_mut(&parent) {
+            dir.files.push(file.clone());
+            dir.size += file.size;
+        }
+        for dir in parent.ancestors().take_while(|a| a.starts_with(root)) {
+            if let Some(dir) = info.tree.get_mut(dir) {
+                dir.combined_size += file.size;
+            }
+        }
+        if let Some(ext) = &file.ext {
+            let filetype = info.filetypes.entry(ext.clone()).or_insert(FileType {
+                ext: ext.clone(),
+                size: 0,
+                files: vec![],
+            });
+            filetype.size += file.size;
+            filetype.files.push(file.clone());
+        }
+        info.combined_size += file.size;
+        compressed.insert(path, entry.compressed);
+        info.files.push(file);
+    }
+
+    info.files_by_size = info.files_by_size();
+    info.types_by_size = info.types_by_size();
+    info.dirs_by_size = info.dirs_by_size();
+    (info, compressed)
+}
+
+/// Add the contents of the archives among the files as virtual subtrees below their paths.
+/// Only the tree is extended, so file lists and sizes still count each archive once.
+pub fn descend_archives(info: &mut DirInfo) {
+    let candidates: Vec<PathBuf> = info
+        .files
+        .iter()
+        .filter(|f| {
+            f.ext
+                .as_deref()
+                .map(|e| ARCHIVE_EXTENSIONS.contains(&e))
+                .unwrap_or_default()
+        })
+        .map(|f| f.path.clone())
+        .collect();
+    for path in candidates {
+        if let Ok((contents, _)) = scan_archive(&path) {
+            info.tree.extend(contents.tree);
+        }
+    }
+    info.dirs_by_size = info.dirs_by_size();
+}
+
+/// Archives whose contents are in the tree, below their own path
+pub fn archive_roots(info: &DirInfo) -> HashSet<PathBuf> {
+    info.files
+        .iter()
+        .filter(|f| info.tree.contains_key(&f.path))
+        .map(|f| f.path.clone())
+        .collect()
+}
+
+/// Whether a path is virtual, inside one of the archives
+pub fn in_archive(path: &Path, archives: &HashSet<PathBuf>) -> bool {
+    path.ancestors().skip(1).any(|a| archives.contains(a))
+}
+
+/// Whether a path is inside an archive that is a file of the scanned tree, rather than a
+/// scan root. Those contents are only in the tree and not in the file list.
+pub fn in_nested_archive(info: &DirInfo, path: &Path, archives: &HashSet<PathBuf>) -> bool {
+    path.ancestors()
+        .skip(1)
+        .filter(|a| archives.contains(*a))
+        .any(|a| {
+            a.parent()
+                .and_then(|p| info.tree.get(p))
+                .map(|d| d.files.iter().any(|f| f.path == a))
+                .unwrap_or_default()
+        })
+}
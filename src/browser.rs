@@ -0,0 +1,270 @@
+//! Picking and validating the scan path, without depending on native dialogs.
+
+use crate::archive::{detect, ArchiveFormat};
+use eframe::egui::{self, Color32, ScrollArea, Ui, Window};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// How many completion candidates are shown below the path input
+const MAX_COMPLETIONS: usize = 8;
+
+/// What a scan path points to
+#[derive(Debug, PartialEq)]
+pub enum ScanTarget {
+    Directory,
+    /// An archive whose contents are listed instead of scanning a directory
+    Archive(ArchiveFormat),
+    Invalid(String),
+}
+
+impl ScanTarget {
+    pub fn is_valid(&self) -> bool {
+        !matches!(self, ScanTarget::Invalid(_))
+    }
+
+    /// A short explanation for the Setup window
+    pub fn description(&self) -> String {
+        match self {
+            ScanTarget::Directory => "Directory".to_string(),
+            ScanTarget::Archive(format) => {
+                format!("{} archive, contents will be listed", format.name())
+            }
+            ScanTarget::Invalid(reason) => reason.clone(),
+        }
+    }
+}
+
+/// Find out whether a path can be scanned, and how.
+pub fn classify_path<P: AsRef<Path>>(path: P) -> ScanTarget {
+    let path = path.as_ref();
+    if path.as_os_str().is_empty() {
+        return ScanTarget::Invalid("No path given".to_string());
+    }
+    match fs::metadata(path) {
+        Ok(meta) if meta.is_dir() => ScanTarget::Directory,
+        Ok(_) => match detect(path) {
+            Some(format) if format.is_supported() => ScanTarget::Archive(format),
+            Some(format) => ScanTarget::Invalid(format!(
+                "{} archive, listing its contents is not supported",
+                format.name()
+            )),
+            None => ScanTarget::Invalid("Not a directory or supported archive".to_string()),
+        },
+        Err(e) => ScanTarget::Invalid(format!("Can't read path: {}", e)),
+    }
+}
+
+/// Sorted subdirectories of a directory, skipping hidden ones unless requested.
+fn subdirs(dir: &Path, show_hidden: bool) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|e| e.path())
+                .filter(|p| p.is_dir())
+                .filter(|p| show_hidden || !is_hidden(p))
+                .collect()
+        })
+        .unwrap_or_default();
+    dirs.sort();
+    dirs
+}
+
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .map(|n| n.to_string_lossy().starts_with('.'))
+        .unwrap_or_default()
+}
+
+/// Directories the (partially typed) input could be completed to.
+pub fn path_completions(input: &str) -> Vec<String> {
+    let path = Path::new(input);
+    // A trailing separator means the user wants to go into that directory
+    let (dir, prefix) = if input.ends_with(std::path::is_separator) {
+        (path, String::new())
+    } else {
+        match (path.parent(), path.file_name()) {
+            (Some(parent), Some(name)) => (parent, name.to_string_lossy().to_string()),
+            _ => return vec![],
+        }
+    };
+
+    subdirs(dir, prefix.starts_with('.'))
+        .into_iter()
+        .filter(|p| {
+            p.file_name()
+                .map(|n| {
+                    let n = n.to_string_lossy();
+                    n.starts_with(&prefix) && n != prefix
+                })
+                .unwrap_or_default()
+        })
+        .take(MAX_COMPLETIONS)
+        .map(|p| p.to_string_lossy().to_string())
+        .collect()
+}
+
+/// The scan path input, with completions and validation.
+/// Directory reads only happen when the input changes.
+pub struct PathInput {
+    checked_path: Option<String>,
+    completions: Vec<String>,
+    target: ScanTarget,
+}
+
+impl Default for PathInput {
+    fn default() -> Self {
+        PathInput {
+            checked_path: None,
+            completions: vec![],
+            target: ScanTarget::Invalid("No path given".to_string()),
+        }
+    }
+}
+
+impl PathInput {
+    fn refresh(&mut self, path: &str) {
+        if self.checked_path.as_deref() != Some(path) {
+            self.completions = path_completions(path);
+            self.target = classify_path(path);
+            self.checked_path = Some(path.to_string());
+        }
+    }
+
+    /// Forget the cached state, e.g. after the filesystem changed
+    pub fn invalidate(&mut self) {
+        self.checked_path = None;
+    }
+
+    /// What the current path points to
+    pub fn target(&mut self, path: &str) -> &ScanTarget {
+        self.refresh(path);
+        &self.target
+    }
+
+    pub fn ui(&mut self, ui: &mut Ui, path: &mut String) {
+        ui.text_edit_singleline(path);
+        self.refresh(path);
+
+        let mut completed = None;
+        for completion in &self.completions {
+            if ui.small_button(completion).clicked() {
+                completed = Some(completion.clone());
+            }
+        }
+        if let Some(completion) = completed {
+            *path = completion;
+            self.refresh(path);
+        }
+
+        let color = if self.target.is_valid() {
+            Color32::from_rgb(140, 220, 140)
+        } else {
+            Color32::from_rgb(255, 120, 120)
+        };
+        ui.colored_label(color, self.target.description());
+    }
+}
+
+/// A directory browser window.
+pub struct DirBrowser {
+    pub open: bool,
+    /// The window title, which also tells browsers apart
+    title: &'static str,
+    current: PathBuf,
+    show_hidden: bool,
+    entries: Option<Vec<PathBuf>>,
+}
+
+impl Default for DirBrowser {
+    fn default() -> Self {
+        DirBrowser {
+            open: false,
+            title: "Choose directory",
+            current: dirs::home_dir().unwrap_or_default(),
+            show_hidden: false,
+            entries: None,
+        }
+    }
+}
+
+impl DirBrowser {
+    /// A browser with its own window title, to be used next to the default one
+    pub fn titled(title: &'static str) -> Self {
+        DirBrowser {
+            title,
+            ..Default::default()
+        }
+    }
+
+    /// Open the browser at a path, or the closest existing ancestor of it
+    pub fn open_at(&mut self, path: &str) {
+        if let Some(existing) = Path::new(path).ancestors().find(|p| p.is_dir()) {
+            self.current = existing.to_path_buf();
+        }
+        self.entries = None;
+        self.open = true;
+    }
+
+    fn navigate(&mut self, dir: PathBuf) {
+        self.current = dir;
+        self.entries = None;
+    }
+
+    /// Show the browser. Choosing a directory writes it into `scan_path`.
+    pub fn show(&mut self, ctx: &egui::CtxRef, scan_path: &mut String) {
+        let mut open = self.open;
+        let mut chosen = false;
+        let mut navigate_to = None;
+
+        if self.entries.is_none() {
+            self.entries = Some(subdirs(&self.current, self.show_hidden));
+        }
+
+        let title = self.title;
+        Window::new(title).open(&mut open).show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if let Some(parent) = self.current.parent() {
+                    if ui.button("Up").clicked() {
+                        navigate_to = Some(parent.to_path_buf());
+                    }
+                }
+                if let Some(home) = dirs::home_dir() {
+                    if ui.button("Home").clicked() {
+                        navigate_to = Some(home);
+                    }
+                }
+                if ui.checkbox(&mut self.show_hidden, "Show hidden").changed() {
+                    self.entries = None;
+                }
+            });
+
+            ui.label(format!("{}", self.current.display()));
+
+            ScrollArea::from_max_height(300.).show(ui, |ui| {
+                for entry in self.entries.iter().flatten() {
+                    let name = entry
+                        .file_name()
+                        .map(|n| n.to_string_lossy().to_string())
+                        .unwrap_or_default();
+                    if ui.button(name).clicked() {
+                        navigate_to = Some(entry.clone());
+                    }
+                }
+            });
+
+            if ui.button("Use this directory").clicked() {
+                chosen = true;
+            }
+        });
+
+        if let Some(dir) = navigate_to {
+            self.navigate(dir);
+        }
+        if chosen {
+            *scan_path = self.current.to_string_lossy().to_string();
+            open = false;
+        }
+        self.open = open;
+    }
+}
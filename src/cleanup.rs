@@ -0,0 +1,259 @@
+//! Recognizing build artifacts and caches that can be regenerated.
+
+use crate::archive::in_archive;
+use crate::scan::{through_symlink, Symlink};
+use diskspace_insight::DirInfo;
+use eframe::egui::{Color32, Ui};
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+
+/// A kind of directory that tools recreate when needed
+pub struct JunkKind {
+    pub dir_name: &'static str,
+    pub description: &'static str,
+    /// Files next to the directory that confirm what it is. Empty if the name is specific enough.
+    pub markers: &'static [&'static str],
+}
+
+pub const JUNK_KINDS: &[JunkKind] = &[
+    JunkKind {
+        dir_name: "target",
+        description: "Rust build output",
+        markers: &["Cargo.toml"],
+    },
+    JunkKind {
+        dir_name: "node_modules",
+        description: "Node.js packages",
+        markers: &["package.json"],
+    },
+    JunkKind {
+        dir_name: ".venv",
+        description: "Python virtual environment",
+        markers: &[],
+    },
+    JunkKind {
+        dir_name: "__pycache__",
+        description: "Python bytecode",
+        markers: &[],
+    },
+    JunkKind {
+        dir_name: ".gradle",
+        description: "Gradle cache",
+        markers: &[],
+    },
+    JunkKind {
+        dir_name: "build",
+        description: "Build output",
+        markers: &[
+            "build.gradle",
+            "build.gradle.kts",
+            "CMakeLists.txt",
+            "setup.py",
+            "pyproject.toml",
+            "package.json",
+        ],
+    },
+    JunkKind {
+        dir_name: ".tox",
+        description: "tox environments",
+        markers: &[],
+    },
+];
+
+/// A directory that looks like it can be removed
+#[derive(Debug, Clone)]
+pub struct JunkDir {
+    /// Index into `JUNK_KINDS`
+    pub kind: usize,
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+fn has_marker(info: &DirInfo, dir: &Path, markers: &[&str]) -> bool {
+    if markers.is_empty() {
+        return true;
+    }
+    dir.parent()
+        .and_then(|parent| info.tree.get(parent))
+        .map(|parent| {
+            parent.files.iter().any(|f| {
+                f.path
+                    .file_name()
+                    .map(|n| markers.iter().any(|m| n == *m))
+                    .unwrap_or_default()
+            })
+        })
+        .unwrap_or_default()
+}
+
+/// All artifact directories in the tree, largest first. Matches inside other matches
+/// (like `node_modules` within `node_modules`) are left out, and so is everything inside the
+/// `archives` or reached through the `symlinks`.
+pub fn find_junk(
+    info: &DirInfo,
+    archives: &HashSet<PathBuf>,
+    symlinks: &HashMap<PathBuf, Symlink>,
+) -> Vec<JunkDir> {
+    let mut paths: Vec<&PathBuf> = info.tree.keys().collect();
+    // Parents before their children
+    paths.sort();
+
+    let mut found: Vec<JunkDir> = vec![];
+    let mut matched: HashSet<&Path> = HashSet::new();
+    for path in paths {
+        if path.ancestors().skip(1).any(|a| matched.contains(a))
+            || in_archive(path, archives)
+            || through_symlink(path, symlinks)
+        {
+            continue;
+        }
+        let name = match path.file_name() {
+            Some(name) => name,
+            None => continue,
+        };
+        let kind = JUNK_KINDS
+            .iter()
+            .position(|k| name == k.dir_name && has_marker(info, path, k.markers));
+        if let Some(kind) = kind {
+            matched.insert(path);
+            found.push(JunkDir {
+                kind,
+                path: path.clone(),
+                size: info.tree[path].combined_size,
+            });
+        }
+    }
+    found.sort_by_key(|j| std::cmp::Reverse(j.size));
+    found
+}
+
+/// Number of directories and bytes per kind, in the order of `JUNK_KINDS`
+pub fn totals_by_kind(junk: &[JunkDir]) -> Vec<(usize, u64)> {
+    let mut totals = vec![(0, 0); JUNK_KINDS.len()];
+    for j in junk {
+        totals[j.kind].0 += 1;
+        totals[j.kind].1 += j.size;
+    }
+    totals
+}
+
+/// How to get rid of directories
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Removal {
+    Trash,
+    Delete,
+}
+
+impl Removal {
+    pub fn apply(&self, path: &Path) -> io::Result<()> {
+        match self {
+            Removal::Trash => crate::trash::move_to_trash(path),
+            Removal::Delete => std::fs::remove_dir_all(path),
+        }
+    }
+}
+
+/// What the cleanup thread reports
+pub enum CleanupMessage {
+    /// Directories handled so far, out of all of them
+    Progress {
+        done: usize,
+        total: usize,
+    },
+    /// A directory is gone
+    Removed(PathBuf),
+    /// A directory could not be removed
+    Failed(PathBuf, String),
+    Done,
+}
+
+/// Remove directories in the background, one after the other
+pub fn spawn_cleanup(removal: Removal, paths: Vec<PathBuf>, sender: Sender<CleanupMessage>) {
+    thread::spawn(move || {
+        let total = paths.len();
+        for (done, path) in paths.into_iter().enumerate() {
+            let _ = sender.send(CleanupMessage::Progress { done, total });
+            let msg = match removal.apply(&path) {
+                Ok(()) => CleanupMessage::Removed(path),
+                Err(e) => CleanupMessage::Failed(path, e.to_string()),
+            };
+            let _ = sender.send(msg);
+        }
+        let _ = sender.send(CleanupMessage::Done);
+    });
+}
+
+/// Deletions a view started, with directories removed in the background. Only what was
+/// actually removed is passed on to `del_sender`, the rest ends up in `errors`.
+pub struct Removals {
+    sender: Sender<CleanupMessage>,
+    receiver: Receiver<CleanupMessage>,
+    /// Directories still being removed
+    pending: usize,
+    pub errors: Vec<String>,
+}
+
+impl Default for Removals {
+    fn default() -> Self {
+        let (sender, receiver) = channel();
+        Removals {
+            sender,
+            receiver,
+            pending: 0,
+            errors: vec![],
+        }
+    }
+}
+
+impl Removals {
+    /// Delete a file right away, or start removing a directory
+    pub fn delete(&mut self, path: &Path, is_dir: bool, del_sender: &Sender<PathBuf>) {
+        if is_dir {
+            self.pending += 1;
+            spawn_cleanup(
+                Removal::Delete,
+                vec![path.to_path_buf()],
+                self.sender.clone(),
+            );
+            return;
+        }
+        match std::fs::remove_file(path) {
+            Ok(()) => {
+                let _ = del_sender.send(path.to_path_buf());
+            }
+            Err(e) => self.errors.push(format!("{} | {}", path.display(), e)),
+        }
+    }
+
+    /// Pass on the directories that are gone. Returns whether some are still being removed.
+    pub fn poll(&mut self, del_sender: &Sender<PathBuf>) -> bool {
+        while let Ok(msg) = self.receiver.try_recv() {
+            match msg {
+                CleanupMessage::Removed(path) => {
+                    self.pending -= 1;
+                    let _ = del_sender.send(path);
+                }
+                CleanupMessage::Failed(path, e) => {
+                    self.pending -= 1;
+                    self.errors.push(format!("{} | {}", path.display(), e));
+                }
+                CleanupMessage::Progress { .. } | CleanupMessage::Done => (),
+            }
+        }
+        self.pending > 0
+    }
+
+    /// Show the directories left to remove and what could not be removed
+    pub fn show(&mut self, ui: &mut Ui, del_sender: &Sender<PathBuf>) {
+        if self.poll(del_sender) {
+            ui.label(format!("Removing {} directories", self.pending));
+            ui.ctx().request_repaint();
+        }
+        for error in &self.errors {
+            ui.colored_label(Color32::from_rgb(255, 120, 120), error.as_str());
+        }
+    }
+}
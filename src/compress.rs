@@ -0,0 +1,176 @@
+//! Estimating how much space compression would save, by test-compressing samples of each file type.
+
+use diskspace_insight::DirInfo;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::thread;
+
+/// How many of the largest file types are sampled. Other files are assumed incompressible.
+const SAMPLED_TYPES: usize = 20;
+/// Files sampled per type
+const SAMPLES_PER_TYPE: usize = 8;
+/// Bytes read from the start of each sampled file
+const SAMPLE_BYTES: u64 = 1024 * 1024;
+pub const ZSTD_LEVEL: i32 = 3;
+
+/// The estimated effect of compressing a group of files
+#[derive(Debug, Clone)]
+pub struct Estimate {
+    /// A file extension or a directory path
+    pub name: String,
+    pub size: u64,
+    pub compressed: u64,
+}
+
+impl Estimate {
+    pub fn savings(&self) -> u64 {
+        self.size.saturating_sub(self.compressed)
+    }
+}
+
+/// Estimates by file type and by directory, largest savings first
+#[derive(Debug, Clone, Default)]
+pub struct CompressionReport {
+    pub types: Vec<Estimate>,
+    pub dirs: Vec<Estimate>,
+}
+
+/// What the estimating thread reports
+pub enum CompressionMessage {
+    /// Files sampled so far
+    Progress {
+        done: usize,
+        total: usize,
+    },
+    Done(CompressionReport),
+}
+
+/// Compressed size divided by sampled size, or None if nothing could be read
+pub fn sample_ratio(paths: &[PathBuf]) -> Option<f32> {
+    let mut raw = 0;
+    let mut compressed = 0;
+    for path in paths {
+        let mut buf = vec![];
+        let read = fs::File::open(path).and_then(|f| f.take(SAMPLE_BYTES).read_to_end(&mut buf));
+        if read.is_err() || buf.is_empty() {
+            continue;
+        }
+        if let Ok(packed) = zstd::encode_all(&buf[..], ZSTD_LEVEL) {
+            raw += buf.len();
+            // Compression never makes it worse, as the file would be stored as it is
+            compressed += packed.len().min(buf.len());
+        }
+    }
+    if raw == 0 {
+        None
+    } else {
+        Some(compressed as f32 / raw as f32)
+    }
+}
+
+/// Pick up to `count` paths spread evenly over a list
+fn spread<T: Clone>(items: &[T], count: usize) -> Vec<T> {
+    if items.len() <= count {
+        return items.to_vec();
+    }
+    (0..count)
+        .map(|i| items[i * items.len() / count].clone())
+        .collect()
+}
+
+/// Apply the ratios of the sampled types to all files, per extension and per directory.
+/// Directories count everything below them, up to the ones in `tree`.
+pub fn build_report(
+    files: &[(PathBuf, u64, Option<String>)],
+    tree: &HashSet<PathBuf>,
+    ratios: &HashMap<String, f32>,
+) -> CompressionReport {
+    let mut types: HashMap<String, Estimate> = HashMap::new();
+    let mut dirs: HashMap<PathBuf, Estimate> = HashMap::new();
+    for (path, size, ext) in files {
+        let ratio = ext
+            .as_ref()
+            .and_then(|e| ratios.get(e))
+            .copied()
+            .unwrap_or(1.);
+        let compressed = (*size as f64 * ratio as f64) as u64;
+        if let Some(ext) = ext {
+            let estimate = types.entry(ext.clone()).or_insert(Estimate {
+                name: ext.clone(),
+                size: 0,
+                compressed: 0,
+            });
+            estimate.size += size;
+            estimate.compressed += compressed;
+        }
+        for dir in path.ancestors().skip(1).take_while(|a| tree.contains(*a)) {
+            let estimate = dirs.entry(dir.to_path_buf()).or_insert(Estimate {
+                name: dir.to_string_lossy().to_string(),
+                size: 0,
+                compressed: 0,
+            });
+            estimate.size += size;
+            estimate.compressed += compressed;
+        }
+    }
+
+    let ranked = |estimates: Vec<Estimate>| {
+        let mut estimates: Vec<Estimate> =
+            estimates.into_iter().filter(|e| e.savings() > 0).collect();
+        estimates.sort_by_key(|e| std::cmp::Reverse(e.savings()));
+        estimates
+    };
+    CompressionReport {
+        types: ranked(types.into_values().collect()),
+        dirs: ranked(dirs.into_values().collect()),
+    }
+}
+
+/// Sample the largest file types of a DirInfo in the background
+pub fn spawn_estimate(info: &DirInfo, sender: Sender<CompressionMessage>) {
+    let samples: Vec<(String, Vec<PathBuf>)> = info
+        .types_by_size
+        .iter()
+        .take(SAMPLED_TYPES)
+        .map(|t| {
+            let paths: Vec<PathBuf> = t.files.iter().map(|f| f.path.clone()).collect();
+            (t.ext.clone(), spread(&paths, SAMPLES_PER_TYPE))
+        })
+        .collect();
+    let files: Vec<(PathBuf, u64, Option<String>)> = info
+        .files
+        .iter()
+        .map(|f| (f.path.clone(), f.size, f.ext.clone()))
+        .collect();
+    let tree: HashSet<PathBuf> = info.tree.keys().cloned().collect();
+
+    thread::spawn(move || {
+        let total = samples.iter().map(|(_, paths)| paths.len()).sum();
+        let mut done = 0;
+        let mut ratios = HashMap::new();
+        for (ext, paths) in samples {
+            if let Some(ratio) = sample_ratio(&paths) {
+                ratios.insert(ext, ratio);
+            }
+            done += paths.len();
+            let _ = sender.send(CompressionMessage::Progress { done, total });
+        }
+        let _ = sender.send(CompressionMessage::Done(build_report(
+            &files, &tree, &ratios,
+        )));
+    });
+}
+
+/// A short name for a directory estimate, like `logs/`
+pub fn dir_label(estimate: &Estimate) -> String {
+    let path = Path::new(&estimate.name);
+    format!(
+        "{}/",
+        path.file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| estimate.name.clone())
+    )
+}
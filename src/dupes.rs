@@ -0,0 +1,368 @@
+//! Finding files with identical content, on a background thread.
+
+use crate::archive::in_archive;
+use crate::scan::{through_symlink, Symlink};
+use diskspace_insight::DirInfo;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::hash::Hasher;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::thread;
+use twox_hash::XxHash64;
+
+/// How much of the start and the end of a file goes into the partial hash
+const PARTIAL_SIZE: u64 = 4096;
+
+/// Files with the same content
+#[derive(Debug, Clone)]
+pub struct DupeGroup {
+    pub size: u64,
+    /// The first path is kept, the others are the extra copies
+    pub paths: Vec<PathBuf>,
+}
+
+impl DupeGroup {
+    /// Bytes freed by removing all but one copy
+    pub fn reclaimable(&self) -> u64 {
+        self.size * self.paths.len().saturating_sub(1) as u64
+    }
+}
+
+/// What the search thread reports
+pub enum DupeMessage {
+    /// Files hashed so far, out of the candidates for the current stage
+    Progress {
+        stage: &'static str,
+        done: usize,
+        total: usize,
+    },
+    Done(Vec<DupeGroup>),
+}
+
+/// Hash the first and last few KB of a file
+fn partial_hash(path: &Path, size: u64) -> io::Result<u64> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = XxHash64::default();
+    let mut buf = vec![0u8; PARTIAL_SIZE.min(size) as usize];
+    file.read_exact(&mut buf)?;
+    hasher.write(&buf);
+    if size > PARTIAL_SIZE * 2 {
+        file.seek(SeekFrom::End(-(PARTIAL_SIZE as i64)))?;
+        file.read_exact(&mut buf)?;
+        hasher.write(&buf);
+    }
+    Ok(hasher.finish())
+}
+
+pub fn full_hash(path: &Path) -> io::Result<u64> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = XxHash64::default();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        hasher.write(&buf[..read]);
+    }
+    Ok(hasher.finish())
+}
+
+/// Split each group by a key, dropping files that can't be read and groups that end up unique
+fn refine(
+    groups: Vec<(u64, Vec<PathBuf>)>,
+    stage: &'static str,
+    progress: &dyn Fn(DupeMessage),
+    key: impl Fn(&Path, u64) -> io::Result<u64>,
+) -> Vec<(u64, Vec<PathBuf>)> {
+    let total = groups.iter().map(|(_, paths)| paths.len()).sum();
+    let mut done = 0;
+    let mut refined = vec![];
+    for (size, paths) in groups {
+        let mut by_key: HashMap<u64, Vec<PathBuf>> = HashMap::new();
+        for path in paths {
+            if let Ok(k) = key(&path, size) {
+                by_key.entry(k).or_default().push(path);
+            }
+            done += 1;
+            if done % 100 == 0 {
+                progress(DupeMessage::Progress { stage, done, total });
+            }
+        }
+        refined.extend(
+            by_key
+                .into_iter()
+                .filter(|(_, paths)| paths.len() > 1)
+                .map(|(_, paths)| (size, paths)),
+        );
+    }
+    refined
+}
+
+/// Keep one path per inode, as hard links share their content already. Symlinks are left out.
+#[cfg(unix)]
+fn without_hard_links(mut paths: Vec<PathBuf>) -> Vec<PathBuf> {
+    use std::os::unix::fs::MetadataExt;
+    let mut seen = HashSet::new();
+    paths.retain(|p| match fs::symlink_metadata(p) {
+        // A symlink is not a copy, removing it frees nothing
+        Ok(meta) if meta.file_type().is_symlink() => false,
+        Ok(meta) => seen.insert((meta.dev(), meta.ino())),
+        Err(_) => false,
+    });
+    paths
+}
+
+#[cfg(not(unix))]
+fn without_hard_links(paths: Vec<PathBuf>) -> Vec<PathBuf> {
+    paths
+}
+
+/// Group files by size, then by partial hash, then by full hash.
+/// Groups are sorted by reclaimable bytes, largest first.
+pub fn find_duplicates(
+    files: Vec<(PathBuf, u64)>,
+    progress: &dyn Fn(DupeMessage),
+) -> Vec<DupeGroup> {
+    let mut by_size: HashMap<u64, Vec<PathBuf>> = HashMap::new();
+    for (path, size) in files {
+        // Empty files are all "identical", but deduplicating them frees nothing
+        if size > 0 {
+            by_size.entry(size).or_default().push(path);
+        }
+    }
+    let candidates: Vec<(u64, Vec<PathBuf>)> = by_size
+        .into_iter()
+        .map(|(size, paths)| (size, without_hard_links(paths)))
+        .filter(|(_, paths)| paths.len() > 1)
+        .collect();
+
+    let candidates = refine(candidates, "Partial hash", progress, |p, size| {
+        partial_hash(p, size)
+    });
+    // Small files were hashed completely already
+    let candidates = refine(candidates, "Full hash", progress, |p, size| {
+        if size <= PARTIAL_SIZE {
+            Ok(0)
+        } else {
+            full_hash(p)
+        }
+    });
+
+    let mut groups: Vec<DupeGroup> = candidates
+        .into_iter()
+        .map(|(size, mut paths)| {
+            paths.sort();
+            DupeGroup { size, paths }
+        })
+        .collect();
+    groups.sort_by_key(|g| std::cmp::Reverse(g.reclaimable()));
+    groups
+}
+
+/// A fingerprint of the recursive contents of every directory: relative names, sizes and
+/// the content hashes the scan computed. Directories with equal fingerprints are identical.
+/// Directories holding a file without a hash, at any depth, get no fingerprint.
+pub fn dir_fingerprints(info: &DirInfo) -> HashMap<PathBuf, u64> {
+    let mut dirs: Vec<&PathBuf> = info.tree.keys().collect();
+    // Children before their parents
+    dirs.sort_by_key(|p| std::cmp::Reverse(p.components().count()));
+
+    let mut fingerprints: HashMap<PathBuf, u64> = HashMap::new();
+    for path in dirs {
+        let dir = &info.tree[path];
+        // A hash of 0 means the content is not known, so neither is the directory's
+        let unknown = dir.files.iter().any(|f| f.hash == 0)
+            || dir
+                .directories
+                .iter()
+                .any(|d| !fingerprints.contains_key(d));
+        if unknown {
+            continue;
+        }
+        let mut entries: Vec<(String, u64, u64)> = dir
+            .files
+            .iter()
+            .map(|f| (file_name(&f.path), f.size, f.hash))
+            .collect();
+        entries.extend(dir.directories.iter().map(|d| {
+            let fingerprint = fingerprints[d];
+            // Tell directories apart from files of the same name
+            (format!("{}/", file_name(d)), 0, fingerprint)
+        }));
+        entries.sort();
+
+        let mut hasher = XxHash64::default();
+        for (name, size, hash) in entries {
+            hasher.write(name.as_bytes());
+            hasher.write_u64(size);
+            hasher.write_u64(hash);
+        }
+        fingerprints.insert(path.clone(), hasher.finish());
+    }
+    fingerprints
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// Directories with identical recursive contents, largest first. Subdirectories of
+/// duplicates are left out, as removing the outer copy takes care of them.
+/// Paths in or inside the `archives` are skipped, and so is whatever the `symlinks` lead to.
+pub fn duplicate_dirs(
+    info: &DirInfo,
+    archives: &HashSet<PathBuf>,
+    symlinks: &HashMap<PathBuf, Symlink>,
+) -> Vec<DupeGroup> {
+    let fingerprints = dir_fingerprints(info);
+    let mut by_fingerprint: HashMap<u64, Vec<PathBuf>> = HashMap::new();
+    for (path, fingerprint) in &fingerprints {
+        // Archive contents can't be removed on their own
+        if archives.contains(path) || in_archive(path, archives) {
+            continue;
+        }
+        // Followed links show the same directories again under another path
+        if through_symlink(path, symlinks) {
+            continue;
+        }
+        // Empty directories are identical, but removing them frees nothing
+        if info.tree[path].combined_size > 0 {
+            by_fingerprint
+                .entry(*fingerprint)
+                .or_default()
+                .push(path.clone());
+        }
+    }
+    by_fingerprint.retain(|_, paths| paths.len() > 1);
+
+    let duplicated: HashSet<&PathBuf> = by_fingerprint.values().flatten().collect();
+    let mut groups: Vec<DupeGroup> = by_fingerprint
+        .values()
+        .filter(|paths| {
+            !paths.iter().all(|p| {
+                p.parent()
+                    .map(|parent| duplicated.contains(&parent.to_path_buf()))
+                    .unwrap_or_default()
+            })
+        })
+        .map(|paths| {
+            let mut paths = paths.clone();
+            paths.sort();
+            DupeGroup {
+                size: info.tree[&paths[0]].combined_size,
+                paths,
+            }
+        })
+        .collect();
+    groups.sort_by_key(|g| std::cmp::Reverse(g.size));
+    groups
+}
+
+/// Search the files of a DirInfo for duplicates in the background. Symlinks and files
+/// reached through them are left out, as they are the same files as their targets.
+pub fn spawn_search(
+    info: &DirInfo,
+    symlinks: &HashMap<PathBuf, Symlink>,
+    sender: Sender<DupeMessage>,
+) {
+    let files: Vec<(PathBuf, u64)> = info
+        .files
+        .iter()
+        .filter(|f| !through_symlink(&f.path, symlinks))
+        .map(|f| (f.path.clone(), f.size))
+        .collect();
+    thread::spawn(move || {
+        let progress = |msg| {
+            let _ = sender.send(msg);
+        };
+        let groups = find_duplicates(files, &progress);
+        let _ = sender.send(DupeMessage::Done(groups));
+    });
+}
+
+/// Replace `copy` with a hard link to `original`. The copy is only replaced once the link exists.
+pub fn hard_link(original: &Path, copy: &Path) -> io::Result<()> {
+    let mut tmp = copy.as_os_str().to_owned();
+    tmp.push(".birdseye-link");
+    let tmp = PathBuf::from(tmp);
+    fs::hard_link(original, &tmp)?;
+    fs::rename(&tmp, copy).inspect_err(|_| {
+        let _ = fs::remove_file(&tmp);
+    })
+}
+
+/// What to do with the extra copies of a group
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DupeAction {
+    Delete,
+    Trash,
+    /// Only possible for files
+    HardLink,
+}
+
+/// What the thread applying an action to a group reports
+#[derive(Debug, PartialEq)]
+pub enum ResolveMessage {
+    /// Copies handled so far, out of all copies of the group
+    Progress {
+        done: usize,
+        total: usize,
+    },
+    /// A copy no longer exists
+    Removed(PathBuf),
+    /// A copy was replaced by a hard link
+    Linked(PathBuf),
+    /// A copy is still there, with the reason
+    Failed(PathBuf, String),
+    Done,
+}
+
+/// Apply an action to all but the first path of a group, reporting each copy
+pub fn resolve(group: &DupeGroup, action: DupeAction, report: &impl Fn(ResolveMessage)) {
+    let (original, copies) = match group.paths.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+    let total = copies.len();
+    for (done, copy) in copies.iter().enumerate() {
+        report(ResolveMessage::Progress { done, total });
+        let result = match action {
+            DupeAction::Delete if copy.is_dir() => fs::remove_dir_all(copy),
+            DupeAction::Delete => fs::remove_file(copy),
+            DupeAction::Trash => crate::trash::move_to_trash(copy),
+            DupeAction::HardLink => hard_link(original, copy),
+        };
+        report(match result {
+            Ok(()) if action == DupeAction::HardLink => ResolveMessage::Linked(copy.clone()),
+            Ok(()) => ResolveMessage::Removed(copy.clone()),
+            Err(e) => ResolveMessage::Failed(copy.clone(), e.to_string()),
+        });
+    }
+}
+
+/// Apply an action to a group in the background
+pub fn spawn_resolve(group: DupeGroup, action: DupeAction, sender: Sender<ResolveMessage>) {
+    thread::spawn(move || {
+        let report = |msg| {
+            let _ = sender.send(msg);
+        };
+        resolve(&group, action, &report);
+        let _ = sender.send(ResolveMessage::Done);
+    });
+}
+
+/// Drop a copy that was handled from the groups, and the groups that have no copies left
+pub fn forget_copy(groups: &mut Vec<DupeGroup>, copy: &Path) {
+    for group in groups.iter_mut() {
+        // The first path is the one that is kept
+        if let Some(i) = group.paths.iter().skip(1).position(|p| p == copy) {
+            group.paths.remove(i + 1);
+        }
+    }
+    groups.retain(|g| g.paths.len() > 1);
+}
@@ -0,0 +1,170 @@
+//! Rescanning directories the user may not read, through a helper mode of this binary that
+//! runs with elevated privileges and passes its results back as snapshots. The UI itself
+//! never runs privileged.
+
+use crate::scan::{self, ScanExtras};
+use crate::snapshot;
+use diskspace_insight::DirInfo;
+use std::ffi::OsString;
+use std::io::{self, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::Sender;
+use std::thread;
+
+/// The first argument that starts the binary as a scan helper instead of the UI
+pub const HELPER_FLAG: &str = "--scan-helper";
+const FOLLOW_FLAG: &str = "--follow-symlinks";
+
+/// What the rescanning thread reports
+pub enum ElevateMessage {
+    /// One directory was scanned
+    Scanned {
+        dir: PathBuf,
+        info: Box<DirInfo>,
+        extras: Box<ScanExtras>,
+    },
+    /// The number of directories scanned
+    Done(Result<usize, String>),
+}
+
+/// Directories whose contents could not be read for lack of permission, without those
+/// inside others. Only directories the scan listed are included.
+pub fn denied_dirs(info: &DirInfo, extras: &ScanExtras) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = extras
+        .errors
+        .iter()
+        .filter(|e| e.kind == io::ErrorKind::PermissionDenied)
+        .filter(|e| info.tree.contains_key(&e.path))
+        .map(|e| e.path.clone())
+        .collect();
+    dirs.sort();
+    dirs.dedup();
+    let mut outermost: Vec<PathBuf> = vec![];
+    for dir in dirs {
+        if !outermost.iter().any(|o| dir.starts_with(o)) {
+            outermost.push(dir);
+        }
+    }
+    outermost
+}
+
+/// Scan the directories given after `HELPER_FLAG` and write the results to `out`.
+/// File contents are not read, so their hashes are 0: the helper only has to find what is there.
+pub fn run_helper(args: &[OsString], out: &mut impl Write) -> io::Result<()> {
+    let follow_symlinks = args.iter().any(|a| a == FOLLOW_FLAG);
+    for dir in args.iter().filter(|a| *a != FOLLOW_FLAG) {
+        let dir = Path::new(dir);
+        let (info, extras) = scan::scan(dir, follow_symlinks, false, |_, _| {}, u128::MAX);
+        snapshot::write(out, dir, &info, &extras)?;
+    }
+    out.flush()
+}
+
+/// Run as the scan helper if the command line starts with `HELPER_FLAG`.
+/// Returns the exit code in that case, and None for a normal start.
+pub fn helper_main(args: &[OsString]) -> Option<i32> {
+    if args.first()? != HELPER_FLAG {
+        return None;
+    }
+    let stdout = io::stdout();
+    match run_helper(&args[1..], &mut stdout.lock()) {
+        Ok(()) => Some(0),
+        Err(e) => {
+            eprintln!("Scan helper failed: {}", e);
+            Some(1)
+        }
+    }
+}
+
+fn find_in_path(name: &str) -> Option<PathBuf> {
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+/// `pkexec` asks for the password in a dialog, `sudo` only works when started from a terminal
+fn elevation_command() -> io::Result<Command> {
+    if let Some(pkexec) = find_in_path("pkexec") {
+        return Ok(Command::new(pkexec));
+    }
+    if let Some(sudo) = find_in_path("sudo") {
+        let mut command = Command::new(sudo);
+        command.arg("--");
+        return Ok(command);
+    }
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        "Neither pkexec nor sudo was found",
+    ))
+}
+
+/// Run the helper on `dirs` with elevated privileges, handing over each scanned directory
+pub fn rescan_elevated(
+    dirs: &[PathBuf],
+    follow_symlinks: bool,
+    scanned: &dyn Fn(PathBuf, DirInfo, ScanExtras),
+) -> io::Result<usize> {
+    let program = std::env::current_exe()?;
+    rescan_through(elevation_command()?, &program, dirs, follow_symlinks, scanned)
+}
+
+/// Run the helper `program` through `command`, which starts the program given as its first
+/// argument
+pub fn rescan_through(
+    mut command: Command,
+    program: &Path,
+    dirs: &[PathBuf],
+    follow_symlinks: bool,
+    scanned: &dyn Fn(PathBuf, DirInfo, ScanExtras),
+) -> io::Result<usize> {
+    // The helper may start in another working directory
+    let cwd = std::env::current_dir()?;
+    command.arg(program).arg(HELPER_FLAG);
+    if follow_symlinks {
+        command.arg(FOLLOW_FLAG);
+    }
+    command
+        .args(dirs.iter().map(|d| cwd.join(d)))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped());
+    let mut child = command.spawn()?;
+
+    let mut count = 0;
+    let read = match child.stdout.take() {
+        Some(stdout) => {
+            let mut reader = BufReader::new(stdout);
+            (|| {
+                while let Some((dir, info, extras)) = snapshot::read(&mut reader)? {
+                    scanned(dir, info, extras);
+                    count += 1;
+                }
+                Ok(())
+            })()
+        }
+        None => Ok(()),
+    };
+    let status = child.wait()?;
+    match status.code() {
+        Some(0) => read.map(|_| count),
+        // pkexec reports a dismissed or refused password dialog like this
+        Some(126) | Some(127) => Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "Not authorized",
+        )),
+        _ => Err(io::Error::other(format!("The helper failed ({})", status))),
+    }
+}
+
+/// Rescan directories with elevated privileges in the background
+pub fn spawn_rescan(dirs: Vec<PathBuf>, follow_symlinks: bool, sender: Sender<ElevateMessage>) {
+    thread::spawn(move || {
+        let scanned = |dir, info, extras| {
+            let info = Box::new(info);
+            let extras = Box::new(extras);
+            let _ = sender.send(ElevateMessage::Scanned { dir, info, extras });
+        };
+        let result = rescan_elevated(&dirs, follow_symlinks, &scanned).map_err(|e| e.to_string());
+        let _ = sender.send(ElevateMessage::Done(result));
+    });
+}
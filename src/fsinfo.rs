@@ -0,0 +1,77 @@
+//! Capacity of the filesystem a path lives on.
+
+use std::path::Path;
+
+/// Space and inode usage of a filesystem
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FsUsage {
+    pub total_bytes: u64,
+    /// Space available to regular users
+    pub free_bytes: u64,
+    /// Space taken by files, not counting what is reserved for root
+    pub used_bytes: u64,
+    pub total_inodes: u64,
+    pub free_inodes: u64,
+    /// Whether the path is the top of the filesystem, so the usage is all below it
+    pub mount_point: bool,
+}
+
+impl FsUsage {
+    pub fn used_inodes(&self) -> u64 {
+        self.total_inodes.saturating_sub(self.free_inodes)
+    }
+
+    /// Share of used inodes, from 0 to 1. Some filesystems have no fixed inode count and report 0.
+    pub fn inode_usage(&self) -> Option<f32> {
+        if self.total_inodes == 0 {
+            None
+        } else {
+            Some(self.used_inodes() as f32 / self.total_inodes as f32)
+        }
+    }
+}
+
+/// The highest directory above `path` that is still on the same filesystem
+#[cfg(unix)]
+pub fn mount_top(path: &Path) -> std::io::Result<std::path::PathBuf> {
+    use std::os::unix::fs::MetadataExt;
+    let device = std::fs::symlink_metadata(path)?.dev();
+    let mut top = path.to_path_buf();
+    for ancestor in path.ancestors().skip(1) {
+        match std::fs::metadata(ancestor) {
+            Ok(meta) if meta.dev() == device => top = ancestor.to_path_buf(),
+            _ => break,
+        }
+    }
+    Ok(top)
+}
+
+#[cfg(unix)]
+pub fn fs_usage<P: AsRef<Path>>(path: P) -> Option<FsUsage> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(path.as_ref().as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: c_path is a valid C string and stat is a properly sized out parameter
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    let fragment_size = stat.f_frsize as u64;
+    let mount_point = std::fs::canonicalize(path.as_ref())
+        .and_then(|p| Ok(mount_top(&p)? == p))
+        .unwrap_or_default();
+    Some(FsUsage {
+        total_bytes: stat.f_blocks as u64 * fragment_size,
+        free_bytes: stat.f_bavail as u64 * fragment_size,
+        used_bytes: (stat.f_blocks as u64).saturating_sub(stat.f_bfree as u64) * fragment_size,
+        total_inodes: stat.f_files as u64,
+        free_inodes: stat.f_ffree as u64,
+        mount_point,
+    })
+}
+
+#[cfg(not(unix))]
+pub fn fs_usage<P: AsRef<Path>>(_path: P) -> Option<FsUsage> {
+    None
+}
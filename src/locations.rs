@@ -0,0 +1,167 @@
+//! Well-known places where caches and logs pile up, mostly on Linux.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::thread;
+
+/// A location that is known to be reclaimable, at least partly
+pub struct Location {
+    pub name: &'static str,
+    pub path: PathBuf,
+    /// What is safe to remove, and how
+    pub description: &'static str,
+    /// Also measure the entries of the directory
+    pub breakdown: bool,
+}
+
+/// The measured size of a location
+#[derive(Debug, Clone, Default)]
+pub struct LocationSize {
+    pub size: u64,
+    /// Entries with their sizes, largest first. Only filled for locations with a breakdown.
+    pub entries: Vec<(PathBuf, u64)>,
+}
+
+/// The known locations for the current user. Not all of them exist on every system.
+pub fn known_locations() -> Vec<Location> {
+    let home = dirs::home_dir().unwrap_or_default();
+    let cache = dirs::cache_dir().unwrap_or_else(|| home.join(".cache"));
+    let data = dirs::data_dir().unwrap_or_else(|| home.join(".local/share"));
+    let cargo = std::env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| home.join(".cargo"));
+
+    let location = |name, path, description| Location {
+        name,
+        path,
+        description,
+        breakdown: false,
+    };
+    vec![
+        Location {
+            name: "User caches",
+            path: cache.clone(),
+            description: "Applications recreate their caches when needed. \
+                Safe to clear while the applications are closed.",
+            breakdown: true,
+        },
+        location(
+            "Thumbnails",
+            cache.join("thumbnails"),
+            "Previews of images and videos, regenerated when a folder is opened. Safe to remove.",
+        ),
+        location(
+            "Trash",
+            data.join("Trash"),
+            "Files that were deleted. Emptying the trash makes them unrecoverable.",
+        ),
+        location(
+            "System journal",
+            PathBuf::from("/var/log/journal"),
+            "Logs kept by journald. Shrink them with `journalctl --vacuum-size=500M` \
+                instead of deleting files.",
+        ),
+        location(
+            "APT package cache",
+            PathBuf::from("/var/cache/apt/archives"),
+            "Downloaded .deb packages that are already installed. Safe to clear with `apt clean`.",
+        ),
+        location(
+            "Cargo registry",
+            cargo.join("registry"),
+            "Downloaded crate sources and the index. Cargo downloads them again when needed.",
+        ),
+        location(
+            "Cargo git checkouts",
+            cargo.join("git"),
+            "Git dependencies of Rust projects. Cargo checks them out again when needed.",
+        ),
+        location(
+            "pip cache",
+            cache.join("pip"),
+            "Downloaded Python packages. Safe to remove, or run `pip cache purge`.",
+        ),
+        location(
+            "npm cache",
+            home.join(".npm"),
+            "Downloaded Node.js packages. Safe to clear with `npm cache clean --force`.",
+        ),
+        location(
+            "Yarn cache",
+            cache.join("yarn"),
+            "Downloaded Node.js packages. Safe to clear with `yarn cache clean`.",
+        ),
+        location(
+            "Flatpak (user)",
+            data.join("flatpak"),
+            "Installed apps and runtimes. Remove leftovers with `flatpak uninstall --unused`, \
+                not by deleting files.",
+        ),
+        location(
+            "Flatpak (system)",
+            PathBuf::from("/var/lib/flatpak"),
+            "Installed apps and runtimes. Remove leftovers with `flatpak uninstall --unused`, \
+                not by deleting files.",
+        ),
+        location(
+            "Snap revisions",
+            PathBuf::from("/var/lib/snapd/snaps"),
+            "Snap packages, including disabled old revisions. Remove those with \
+                `snap remove <name> --revision <rev>`, not by deleting files.",
+        ),
+    ]
+}
+
+/// The size of a file or directory, without following symlinks. Unreadable parts are skipped.
+pub fn disk_usage(path: &Path) -> u64 {
+    let meta = match fs::symlink_metadata(path) {
+        Ok(meta) => meta,
+        Err(_) => return 0,
+    };
+    if !meta.is_dir() {
+        return meta.len();
+    }
+    fs::read_dir(path)
+        .map(|entries| entries.flatten().map(|e| disk_usage(&e.path())).sum())
+        .unwrap_or_default()
+}
+
+pub fn measure(location: &Location) -> LocationSize {
+    if !location.breakdown {
+        return LocationSize {
+            size: disk_usage(&location.path),
+            entries: vec![],
+        };
+    }
+    let mut entries: Vec<(PathBuf, u64)> = fs::read_dir(&location.path)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|e| (e.path(), disk_usage(&e.path())))
+                .collect()
+        })
+        .unwrap_or_default();
+    entries.sort_by_key(|(_, size)| std::cmp::Reverse(*size));
+    LocationSize {
+        size: entries.iter().map(|(_, size)| size).sum(),
+        entries,
+    }
+}
+
+/// Measure the existing locations in the background. Each result is sent with the
+/// index of its location as soon as it is known. Returns how many results to expect.
+pub fn spawn_measure(sender: Sender<(usize, LocationSize)>) -> usize {
+    let existing: Vec<(usize, Location)> = known_locations()
+        .into_iter()
+        .enumerate()
+        .filter(|(_, l)| l.path.exists())
+        .collect();
+    let count = existing.len();
+    thread::spawn(move || {
+        for (i, location) in existing {
+            let _ = sender.send((i, measure(&location)));
+        }
+    });
+    count
+}
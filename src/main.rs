@@ -2,71 +2,451 @@
 #![windows_subsystem = "windows"]
 
 use bytesize::ByteSize;
-use dirs;
-use diskspace_insight;
 use diskspace_insight::{DirInfo, Directory, File};
 use eframe::egui::Color32;
 // use egui::paint::color::Srgba;
 // use egui::{paint::PaintCmd, Button, Checkbox, Label, Slider, Style, TextStyle, Ui, Window, Stroke};
-// use egui_glium::storage::FileStorage;
-use env_logger;
-use log::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
 use std::sync::mpsc::{channel, Receiver, Sender};
 use std::thread;
 use std::path::Path;
 
 use eframe::{
-    egui::{self, Ui, Button, Checkbox, Label, Slider, Style, TextStyle, Window, Stroke, color::Rgba, paint::Shape},
+    egui::{self, Ui, Button, Checkbox, Label, ScrollArea, Slider, Style, TextStyle, Window, Stroke, paint::Shape},
     epi,
 };
 
+mod age;
+use age::{age_color, BarStyle};
+mod archive;
+use archive::{archive_roots, in_archive, in_nested_archive, ratio, ArchiveInfo};
+mod browser;
+mod cleanup;
+use cleanup::{
+    find_junk, spawn_cleanup, totals_by_kind, CleanupMessage, JunkDir, Removal, JUNK_KINDS,
+};
+mod compress;
+use compress::{dir_label, spawn_estimate, CompressionMessage, CompressionReport};
+mod dupes;
+use dupes::{
+    duplicate_dirs, forget_copy, spawn_resolve, spawn_search, DupeAction, DupeGroup, DupeMessage,
+    ResolveMessage,
+};
+mod elevate;
+use elevate::{denied_dirs, spawn_rescan, ElevateMessage};
+mod fsinfo;
+use browser::{DirBrowser, PathInput};
+use fsinfo::{fs_usage, FsUsage};
+mod locations;
+use locations::{known_locations, spawn_measure, Location, LocationSize};
+mod owners;
+use owners::{group_names, owner_report, user_names, OwnerReport};
+mod pack;
+use pack::{default_destination, spawn_pack, PackMessage};
+mod relocate;
+use relocate::{spawn_move, spawn_relocate, MoveMessage};
+mod roots;
+use roots::{
+    fill_in, graft, insert_file, insert_scanned, merge_infos, overlapping_root, subtree_info,
+    update_stats, Metric, ScanRoot,
+};
+mod scan;
+use scan::{ScanExtras, Symlink};
+mod snapshot;
+mod table;
+use table::{dir_rows, file_rows, Table};
+mod timeline;
+use timeline::{bucket_files, paint_count_bar_before_next, Bucket, BucketStats, Granularity};
+mod trash;
+use trash::{TrashAction, TrashItem, TrashMessage};
+mod tree;
+use tree::{DirTree, TreeAction};
+
 #[cfg(test)]
 mod tests;
 
+/// How many recently scanned paths are remembered
+const MAX_RECENT_PATHS: usize = 10;
+
+/// The app state. Settings are persisted through eframe's storage,
+/// everything else is runtime state and gets skipped.
+#[derive(Deserialize, Serialize)]
+#[serde(default)]
 struct MyApp {
     scan_path: String,
     max_types: i32,
     max_files: i32,
     max_dirs: i32,
+    /// Files listed per month or year in the timeline
+    max_timeline_files: i32,
+    /// Entries listed in the archive window
+    max_archive_entries: i32,
+    /// Types and directories listed in the compression window
+    max_compression_estimates: i32,
+    /// Files listed per user in the owners window
+    max_owner_files: i32,
+    /// Groups listed in the duplicates window
+    max_dupe_groups: i32,
+    /// Entries listed per known location
+    max_location_entries: i32,
+    /// What directories are ranked by
+    metric: Metric,
+    /// Tint size bars by modification age
+    color_by_age: bool,
+    /// List the contents of archives found while scanning directories
+    descend_archives: bool,
+    /// Scan the targets of symlinks, instead of listing the links only
+    follow_symlinks: bool,
+    /// The merged results of all scan roots, if there are several.
+    /// A single root is shown from its own results.
+    #[serde(skip)]
     info: DirInfo,
+    #[serde(skip)]
+    roots: Vec<ScanRoot>,
+    /// What the scans of several roots found besides `info`
+    #[serde(skip)]
+    scan_extras: ScanExtras,
+    /// The directory all views are scoped to, if any
+    #[serde(skip)]
+    focus: Option<PathBuf>,
+    /// `info`, reduced to the focused directory
+    #[serde(skip)]
+    focus_info: Option<DirInfo>,
+    /// Archives whose contents are browsable: archive roots, and archives found
+    /// while scanning with `descend_archives`
+    #[serde(skip)]
+    archives: HashSet<PathBuf>,
+    /// The directories of the current view, ranked by item count
+    #[serde(skip)]
+    dirs_by_count: Option<Vec<PathBuf>>,
+    /// The newest modification per file type of the current view
+    #[serde(skip)]
+    type_newest: Option<HashMap<String, SystemTime>>,
+    /// Whether the timeline groups files by month or by year
+    timeline_granularity: Granularity,
+    /// The files of the current view, grouped by modification date
+    #[serde(skip)]
+    timeline: Option<BTreeMap<Bucket, BucketStats>>,
+    #[serde(skip)]
+    dir_tree: DirTree,
+    /// Groups of identical files, most reclaimable first
+    #[serde(skip)]
+    dupes: Vec<DupeGroup>,
+    /// Identical directories of the current view, largest first
+    #[serde(skip)]
+    dupe_dirs: Option<Vec<DupeGroup>>,
+    /// Show duplicate directories instead of files
+    dupe_dirs_view: bool,
+    /// A group whose copies wait for the user to confirm what happens to them
+    #[serde(skip)]
+    dupes_pending: Option<(usize, DupeAction)>,
+    /// Copies the last action could not handle, with the reason
+    #[serde(skip)]
+    dupes_errors: Vec<String>,
+    /// Build artifacts and caches of the current view, largest first
+    #[serde(skip)]
+    junk: Option<Vec<JunkDir>>,
+    /// Directories waiting for the user to confirm their removal
+    #[serde(skip)]
+    junk_pending: Option<(Removal, Vec<PathBuf>)>,
+    /// The state of a running cleanup
+    #[serde(skip)]
+    cleanup_progress: Option<String>,
+    /// Directories the last cleanup could not remove, with the reason
+    #[serde(skip)]
+    cleanup_errors: Vec<String>,
+    /// Waiting for the user to confirm removing all broken symlinks
+    #[serde(skip)]
+    broken_links_confirm: bool,
+    /// Whether the window listing unreadable paths is open
+    #[serde(skip)]
+    show_errors: bool,
+    /// The state of a running elevated rescan
+    #[serde(skip)]
+    elevate_progress: Option<String>,
+    /// How the last elevated rescan ended
+    #[serde(skip)]
+    elevate_result: Option<String>,
+    /// Well-known cache and log locations
+    #[serde(skip)]
+    locations: Vec<Location>,
+    /// Measured sizes, by index into `locations`
+    #[serde(skip)]
+    location_sizes: HashMap<usize, LocationSize>,
+    /// How many locations are still being measured
+    #[serde(skip)]
+    locations_pending: usize,
+    /// Everything in the trash directories, None until listed
+    #[serde(skip)]
+    trash_items: Option<Vec<TrashItem>>,
+    /// Trashed paths the user selected
+    #[serde(skip)]
+    trash_selected: HashSet<PathBuf>,
+    /// Waiting for the user to confirm purging the selection
+    #[serde(skip)]
+    trash_confirm: bool,
+    /// Items the last restore or purge could not handle, with the reason
+    #[serde(skip)]
+    trash_errors: Vec<String>,
+    /// The state of a running restore or purge
+    #[serde(skip)]
+    trash_progress: Option<String>,
+    /// Estimated compression savings of the current view
+    #[serde(skip)]
+    compression: Option<CompressionReport>,
+    /// The state of a running compression estimate
+    #[serde(skip)]
+    compression_progress: Option<String>,
+    /// Sizes per user and group of the current view
+    #[serde(skip)]
+    owners: Option<OwnerReport>,
+    /// The directory to pack, with the archive path the user chose
+    #[serde(skip)]
+    pack_request: Option<(PathBuf, String)>,
+    /// Remove directories once they are packed and verified
+    #[serde(skip)]
+    pack_remove: bool,
+    /// The state of a running pack job
+    #[serde(skip)]
+    pack_progress: Option<String>,
+    /// How the last pack job ended
+    #[serde(skip)]
+    pack_result: Option<String>,
+    /// The file or directory to move, with the target directory the user chose
+    #[serde(skip)]
+    move_request: Option<(PathBuf, String)>,
+    /// Leave a symlink where the moved directory was
+    #[serde(skip)]
+    move_link: bool,
+    /// The state of a running move
+    #[serde(skip)]
+    move_progress: Option<String>,
+    /// How the last move ended
+    #[serde(skip)]
+    move_result: Option<String>,
+    #[serde(skip)]
+    move_browser: DirBrowser,
+    /// The state of a running duplicate search
+    #[serde(skip)]
+    dupes_progress: Option<String>,
+    /// The state of an action running on the copies of a duplicate group
+    #[serde(skip)]
+    resolve_progress: Option<String>,
     allow_delete: bool,
     filter_chain: Vec<Filter>,
-    dirinfo_receiver: Receiver<DirInfo>,
-    dirinfo_sender: Sender<DirInfo>,
-    ready_receiver: Receiver<bool>,
-    ready_sender: Sender<bool>,
+    /// Recently scanned paths, most recent first
+    recent_paths: Vec<String>,
+    /// User-defined scan locations
+    bookmarks: Vec<String>,
+    /// Show the files as a sortable table
+    files_table_view: bool,
+    /// Show the directories as a sortable table instead of a tree
+    dirs_table_view: bool,
+    files_table: Table,
+    dirs_table: Table,
+    #[serde(skip)]
+    path_input: PathInput,
+    #[serde(skip)]
+    dir_browser: DirBrowser,
+    #[serde(skip)]
+    dirinfo_receiver: Receiver<(String, DirInfo, ScanExtras)>,
+    #[serde(skip)]
+    dirinfo_sender: Sender<(String, DirInfo, ScanExtras)>,
+    #[serde(skip)]
+    archive_receiver: Receiver<(String, Result<ArchiveInfo, String>)>,
+    #[serde(skip)]
+    archive_sender: Sender<(String, Result<ArchiveInfo, String>)>,
+    #[serde(skip)]
+    ready_receiver: Receiver<String>,
+    #[serde(skip)]
+    ready_sender: Sender<String>,
+    #[serde(skip)]
     del_receiver: Receiver<PathBuf>,
+    #[serde(skip)]
     del_sender: Sender<PathBuf>,
+    #[serde(skip)]
+    dupes_receiver: Receiver<DupeMessage>,
+    #[serde(skip)]
+    dupes_sender: Sender<DupeMessage>,
+    #[serde(skip)]
+    resolve_receiver: Receiver<ResolveMessage>,
+    #[serde(skip)]
+    resolve_sender: Sender<ResolveMessage>,
+    #[serde(skip)]
+    locations_receiver: Receiver<(usize, LocationSize)>,
+    #[serde(skip)]
+    locations_sender: Sender<(usize, LocationSize)>,
+    #[serde(skip)]
+    compression_receiver: Receiver<CompressionMessage>,
+    #[serde(skip)]
+    compression_sender: Sender<CompressionMessage>,
+    #[serde(skip)]
+    pack_receiver: Receiver<PackMessage>,
+    #[serde(skip)]
+    pack_sender: Sender<PackMessage>,
+    #[serde(skip)]
+    move_request_receiver: Receiver<PathBuf>,
+    #[serde(skip)]
+    move_request_sender: Sender<PathBuf>,
+    #[serde(skip)]
+    move_receiver: Receiver<MoveMessage>,
+    #[serde(skip)]
+    move_sender: Sender<MoveMessage>,
+    #[serde(skip)]
+    cleanup_receiver: Receiver<CleanupMessage>,
+    #[serde(skip)]
+    cleanup_sender: Sender<CleanupMessage>,
+    #[serde(skip)]
+    elevate_receiver: Receiver<ElevateMessage>,
+    #[serde(skip)]
+    elevate_sender: Sender<ElevateMessage>,
+    #[serde(skip)]
+    trash_receiver: Receiver<Vec<TrashItem>>,
+    #[serde(skip)]
+    trash_sender: Sender<Vec<TrashItem>>,
+    #[serde(skip)]
+    trash_action_receiver: Receiver<TrashMessage>,
+    #[serde(skip)]
+    trash_action_sender: Sender<TrashMessage>,
+    #[serde(skip)]
+    restored_receiver: Receiver<(PathBuf, DirInfo, ScanExtras)>,
+    #[serde(skip)]
+    restored_sender: Sender<(PathBuf, DirInfo, ScanExtras)>,
 
+    #[serde(skip)]
     ready: bool,
 }
 
 impl Default for MyApp {
     fn default() -> MyApp {
-        let (s, r): (Sender<DirInfo>, Receiver<DirInfo>) = channel();
-        let (bs, br): (Sender<bool>, Receiver<bool>) = channel();
+        let (s, r) = channel();
+        let (bs, br): (Sender<String>, Receiver<String>) = channel();
         let (ds, dr): (Sender<PathBuf>, Receiver<PathBuf>) = channel();
+        let (archive_sender, archive_receiver) = channel();
+        let (dupes_sender, dupes_receiver) = channel();
+        let (resolve_sender, resolve_receiver) = channel();
+        let (locations_sender, locations_receiver) = channel();
+        let (compression_sender, compression_receiver) = channel();
+        let (pack_sender, pack_receiver) = channel();
+        let (move_request_sender, move_request_receiver) = channel();
+        let (move_sender, move_receiver) = channel();
+        let (cleanup_sender, cleanup_receiver) = channel();
+        let (elevate_sender, elevate_receiver) = channel();
+        let (trash_sender, trash_receiver) = channel();
+        let (trash_action_sender, trash_action_receiver) = channel();
+        let (restored_sender, restored_receiver) = channel();
         MyApp {
             scan_path: String::default(),
             max_types: 10,
             max_files: 10,
             max_dirs: 10,
+            max_timeline_files: 10,
+            max_archive_entries: 10,
+            max_compression_estimates: 10,
+            max_owner_files: 10,
+            max_dupe_groups: 10,
+            max_location_entries: 10,
+            metric: Metric::Size,
+            color_by_age: false,
+            descend_archives: false,
+            follow_symlinks: false,
             info: DirInfo::new(),
+            roots: vec![],
+            scan_extras: ScanExtras::default(),
+            focus: None,
+            focus_info: None,
+            archives: HashSet::new(),
+            dirs_by_count: None,
+            type_newest: None,
+            timeline_granularity: Granularity::Month,
+            timeline: None,
+            dir_tree: DirTree::default(),
+            dupes: vec![],
+            dupe_dirs: None,
+            dupe_dirs_view: false,
+            dupes_pending: None,
+            dupes_errors: vec![],
+            junk: None,
+            junk_pending: None,
+            cleanup_progress: None,
+            cleanup_errors: vec![],
+            broken_links_confirm: false,
+            show_errors: false,
+            elevate_progress: None,
+            elevate_result: None,
+            locations: known_locations(),
+            location_sizes: HashMap::new(),
+            locations_pending: 0,
+            trash_items: None,
+            trash_selected: HashSet::new(),
+            trash_confirm: false,
+            trash_errors: vec![],
+            trash_progress: None,
+            compression: None,
+            owners: None,
+            compression_progress: None,
+            pack_request: None,
+            pack_remove: false,
+            pack_progress: None,
+            pack_result: None,
+            move_request: None,
+            move_link: false,
+            move_progress: None,
+            move_result: None,
+            move_browser: DirBrowser::titled("Choose move target"),
+            dupes_progress: None,
+            resolve_progress: None,
             allow_delete: false,
             filter_chain: vec![],
+            recent_paths: vec![],
+            bookmarks: vec![],
+            files_table_view: false,
+            dirs_table_view: false,
+            files_table: Table::default(),
+            dirs_table: Table::default(),
+            path_input: PathInput::default(),
+            dir_browser: DirBrowser::default(),
             dirinfo_receiver: r,
             dirinfo_sender: s,
+            archive_receiver,
+            archive_sender,
             ready_receiver: br,
             ready_sender: bs,
             del_receiver: dr,
             del_sender: ds,
+            dupes_receiver,
+            dupes_sender,
+            resolve_receiver,
+            resolve_sender,
+            locations_receiver,
+            locations_sender,
+            compression_receiver,
+            compression_sender,
+            pack_receiver,
+            pack_sender,
+            move_request_receiver,
+            move_request_sender,
+            move_receiver,
+            move_sender,
+            cleanup_receiver,
+            cleanup_sender,
+            elevate_receiver,
+            elevate_sender,
+            trash_receiver,
+            trash_sender,
+            trash_action_receiver,
+            trash_action_sender,
+            restored_receiver,
+            restored_sender,
             ready: true,
         }
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
 enum Filter {
     MinAge(i32),
     MaxAge(i32),
@@ -74,79 +454,145 @@ enum Filter {
     MaxResults(i32),
 }
 
-fn draw_file(ui: &mut Ui, file: &File, allow_delete: bool, del_sender: Sender<PathBuf>) {
+/// Put a path on top of the recently scanned paths, dropping duplicates and the oldest entries
+fn remember_path(recent_paths: &mut Vec<String>, path: &str) {
+    recent_paths.retain(|p| p != path);
+    recent_paths.insert(0, path.to_string());
+    recent_paths.truncate(MAX_RECENT_PATHS);
+}
+
+/// Draw a file row, with the target if it is a symlink. Deleting and moving are offered
+/// with `allow_delete`, unless the file is inside one of the `archives`; moves are requested
+/// through `move_requests`, as they need a target first.
+fn draw_file(
+    ui: &mut Ui,
+    file: &File,
+    link: Option<&Symlink>,
+    allow_delete: bool,
+    archives: &HashSet<PathBuf>,
+    del_sender: Sender<PathBuf>,
+    move_requests: Sender<PathBuf>,
+) {
+    let allow_delete = allow_delete && !in_archive(&file.path, archives);
     ui.horizontal(|ui| {
         // ui.label(format!("{:<10}MB", file.size / 1024 / 1024));
         ui.add(Label::new(format!("{}", ByteSize(file.size))).text_style(TextStyle::Monospace));
         // ui.expand_to_size(egui::math::Vec2::new(100.,10.));
-        if allow_delete {
-            if ui.button("Del").clicked() {
-                let _ = std::fs::remove_file(&file.path);
-                let _ = del_sender.send(file.path.to_path_buf());
-            }
+        if allow_delete && ui.button("Del").clicked() && std::fs::remove_file(&file.path).is_ok() {
+            let _ = del_sender.send(file.path.to_path_buf());
+        }
+        if allow_delete && ui.button("Move to...").clicked() {
+            let _ = move_requests.send(file.path.to_path_buf());
         }
         ui.label(format!("{}", file.path.display()));
+        if let Some(link) = link {
+            draw_link_target(ui, link);
+        }
     });
 }
 
-fn draw_dir(
-    ui: &mut Ui,
-    dir: &Directory,
-    info: &DirInfo,
-    allow_delete: bool,
-    accent_color: Color32,
-    del_sender: Sender<PathBuf>,
-) {
-    let scale = dir.combined_size as f32 / info.combined_size as f32;
-
-    paint_size_bar_before_next(ui, scale, accent_color);
-
-    // Sort subdirs
-    ui.collapsing(
-        format!(
-            "{} | {} | {}%",
-            dir.path
-                .file_name()
-                .map(|d| d.to_string_lossy().to_string())
-                .unwrap_or_default(),
-            ByteSize(dir.combined_size),
-            (scale * 100.) as u8
-        ),
-        |ui| {
-            if allow_delete {
-                if ui
-                    .button(&format!(
-                        "Del {}",
-                        dir.path
-                            .file_name()
-                            .map(|d| d.to_string_lossy().to_string())
-                            .unwrap_or_default(),
-                    ))
-                    .clicked()
-                {
-                    let _ = std::fs::remove_dir_all(&dir.path);
-                    let _ = del_sender.send(dir.path.to_path_buf());
-                }
-            }
-            for subdir in &dir.sorted_subdirs(info) {
-                draw_dir(
-                    ui,
-                    subdir,
-                    info,
-                    allow_delete,
-                    accent_color,
-                    del_sender.clone(),
-                );
-            }
+/// Show where a symlink points, and whether the target is missing
+fn draw_link_target(ui: &mut Ui, link: &Symlink) {
+    if link.broken {
+        let text = format!("-> {} (broken)", link.target.display());
+        ui.colored_label(Color32::from_rgb(255, 120, 120), text);
+    } else {
+        ui.colored_label(
+            Color32::from_rgb(80, 160, 220),
+            format!("-> {}", link.target.display()),
+        );
+    }
+}
 
-            for (i, file) in dir.sorted_files().iter().enumerate() {
-                if i as i32 > 10 {
-                    break;
+/// Draw the path from the scan root to the focused directory.
+/// Returns the new focus if a crumb was clicked, `Some(None)` meaning no focus.
+fn draw_breadcrumbs(
+    ui: &mut Ui,
+    roots: &[ScanRoot],
+    focus: &Option<PathBuf>,
+) -> Option<Option<PathBuf>> {
+    let mut navigate = None;
+    ui.horizontal_wrapped(|ui| {
+        if ui.small_button("All").clicked() {
+            navigate = Some(None);
+        }
+        if let Some(focus) = focus {
+            let root = roots
+                .iter()
+                .map(|r| Path::new(&r.path))
+                .find(|r| focus.starts_with(r));
+            let crumbs: Vec<&Path> = focus
+                .ancestors()
+                .take_while(|a| root.map(|r| a.starts_with(r)).unwrap_or(true))
+                .collect();
+            for crumb in crumbs.iter().rev() {
+                ui.label(">");
+                let name = match crumb.file_name() {
+                    Some(name) if Some(*crumb) != root => name.to_string_lossy().to_string(),
+                    _ => crumb.to_string_lossy().to_string(),
+                };
+                if ui.small_button(name).clicked() {
+                    navigate = Some(Some(crumb.to_path_buf()));
                 }
-                draw_file(ui, file, allow_delete, del_sender.clone());
             }
-        },
-    );
+        }
+    });
+    navigate
+}
+
+fn draw_metric_choice(ui: &mut Ui, metric: &mut Metric) {
+    ui.horizontal(|ui| {
+        ui.label("Rank by");
+        ui.radio_value(metric, Metric::Size, "Size");
+        ui.radio_value(metric, Metric::Count, "Item count");
+    });
+}
+
+/// Show free space and inode usage of a root's filesystem
+fn draw_fs_usage(ui: &mut Ui, usage: &FsUsage) {
+    let inodes = match usage.inode_usage() {
+        Some(share) => format!(
+            "{} of {} inodes used ({:.1}%)",
+            usage.used_inodes(),
+            usage.total_inodes,
+            share * 100.
+        ),
+        None => "no inode limit".to_string(),
+    };
+    ui.label(format!(
+        "{} of {} free | {}",
+        ByteSize(usage.free_bytes),
+        ByteSize(usage.total_bytes),
+        inodes
+    ));
+}
+
+/// The newest modification time of each file type
+fn newest_by_type(info: &DirInfo) -> HashMap<String, SystemTime> {
+    info.types_by_size
+        .iter()
+        .map(|t| {
+            let newest = t.files.iter().map(|f| f.modified).max();
+            (t.ext.clone(), newest.unwrap_or(SystemTime::UNIX_EPOCH))
+        })
+        .collect()
+}
+
+/// Explain the age colors
+fn draw_age_legend(ui: &mut Ui) {
+    let now = SystemTime::now();
+    let day = Duration::from_secs(86400);
+    for (label, age) in &[
+        ("today", 0),
+        ("month", 30),
+        ("year", 365),
+        ("5+ years", 5 * 365),
+    ] {
+        let time = now
+            .checked_sub(day * *age)
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        ui.colored_label(age_color(time), *label);
+    }
 }
 
 fn gen_light_style() -> Style {
@@ -170,46 +616,124 @@ fn paint_size_bar_before_next(ui: &mut Ui, scale: f32, color: Color32) {
     });
 }
 
-fn get_dirinfo(path: &String, sender: Sender<DirInfo>, ready: Sender<bool>) {
+/// Scan a root in the background. Results are sent along with the root path.
+/// Archives are listed instead, and their details are sent through `archive`.
+/// With `descend_archives`, archives found in a directory become browsable subtrees.
+/// Symlinks are only listed, unless `follow_symlinks` is set.
+fn get_dirinfo(
+    path: &str,
+    descend_archives: bool,
+    follow_symlinks: bool,
+    sender: Sender<(String, DirInfo, ScanExtras)>,
+    archive: Sender<(String, Result<ArchiveInfo, String>)>,
+    ready: Sender<String>,
+) {
     let s = sender.clone();
     let r = ready.clone();
-    let p = path.clone();
+    let p = path.to_string();
 
     thread::spawn(move || {
         let timer = std::time::Instant::now();
         if Path::new(&p).is_dir() {
-            let final_info = diskspace_insight::scan_callback(
-                &p,
-                |d| {
-                    let _ = s.send(d.clone());
+            let (mut final_info, mut extras) = scan::scan(
+                Path::new(&p),
+                follow_symlinks,
+                true,
+                |d, e| {
+                    // The views list the results as they are, so they need rankings
+                    let mut d = d.clone();
+                    scan::rank(&mut d);
+                    let _ = s.send((p.clone(), d, e.clone()));
                 },
                 2000,
             );
     
             // let final_info = diskspace_insight::scan(&p);
+            if descend_archives {
+                archive::descend_archives(&mut final_info);
+                for archive in archive_roots(&final_info) {
+                    update_stats(&final_info, &mut extras.stats, &archive);
+                }
+            }
     
-            let _ = s.send(final_info);
-            let _ = r.send(true);
+            let _ = s.send((p.clone(), final_info, extras));
+            let _ = r.send(p.clone());
 
 
         } else {
-            let final_info = diskspace_insight::scan_archive(
-                &p
-            );
-    
-    
-            let _ = s.send(final_info);
-            let _ = r.send(true);
+            match archive::scan_archive(Path::new(&p)) {
+                Ok((final_info, archive_info)) => {
+                    let mut extras = ScanExtras::default();
+                    update_stats(&final_info, &mut extras.stats, Path::new(&p));
+                    let _ = s.send((p.clone(), final_info, extras));
+                    let _ = archive.send((p.clone(), Ok(archive_info)));
+                }
+                Err(e) => {
+                    let _ = archive.send((p.clone(), Err(e.to_string())));
+                }
+            }
+            let _ = r.send(p.clone());
         }
 
         println!("Done scanning in {} s", timer.elapsed().as_secs_f32());
     });
 }
 
+/// Scan paths that came back from the trash in the background, so they can be added to the roots
+fn scan_restored(
+    paths: Vec<PathBuf>,
+    follow_symlinks: bool,
+    sender: Sender<(PathBuf, DirInfo, ScanExtras)>,
+) {
+    thread::spawn(move || {
+        for path in paths {
+            let (info, extras) = scan::scan(&path, follow_symlinks, true, |_, _| {}, u128::MAX);
+            let _ = sender.send((path, info, extras));
+        }
+    });
+}
+
+/// What kind of archive a root is, and how well it is compressed
+fn draw_archive_summary(ui: &mut Ui, root: &ScanRoot) {
+    if let Some(error) = &root.error {
+        ui.colored_label(Color32::from_rgb(255, 120, 120), error.as_str());
+    }
+    if let Some(archive) = &root.archive {
+        let unpacked = root.info.combined_size;
+        ui.label(format!(
+            "{} archive | {} packed | {} unpacked{}",
+            archive.format.name(),
+            ByteSize(archive.archive_size),
+            ByteSize(unpacked),
+            ratio(unpacked, archive.archive_size)
+                .map(|r| format!(" | ratio {:.1}x", r))
+                .unwrap_or_default()
+        ));
+    }
+}
+
 impl epi::App for MyApp {
 
     fn name(&self) -> &str {
-        "egui template"
+        "birdseye"
+    }
+
+    /// Restore the settings of the last session, if there are any.
+    fn setup(
+        &mut self,
+        _ctx: &egui::CtxRef,
+        _frame: &mut epi::Frame<'_>,
+        storage: Option<&dyn epi::Storage>,
+    ) {
+        if let Some(app) = storage.and_then(|s| epi::get_value::<MyApp>(s, epi::APP_KEY)) {
+            *self = app;
+        }
+        trash::spawn_list(self.trash_sender.clone());
+    }
+
+    /// Store the settings. Window positions are saved by eframe along with the egui memory.
+    fn save(&mut self, storage: &mut dyn epi::Storage) {
+        epi::set_value(storage, epi::APP_KEY, self);
     }
 
     /// This function will be called whenever the Ui needs to be shown,
@@ -218,11 +742,15 @@ impl epi::App for MyApp {
         &mut self,
         // ui: &mut egui::Ui,
         ctx: &egui::CtxRef,
-        frame: &mut epi::Frame<'_>,
+        _frame: &mut epi::Frame<'_>,
         // _info: &egui::app::BackendInfo,
         // _tex_allocator: Option<&mut dyn egui::app::TextureAllocator>,
     ) {
         let accent_color = Color32::from_rgb(120, 50, 200);
+        let bar_style = BarStyle {
+            accent: accent_color,
+            by_age: self.color_by_age,
+        };
 
         // ui.style_mut().visuals.ui(ui);
 
@@ -233,15 +761,104 @@ impl epi::App for MyApp {
             max_types,
             max_files,
             max_dirs,
+            max_timeline_files,
+            max_archive_entries,
+            max_compression_estimates,
+            max_owner_files,
+            max_dupe_groups,
+            max_location_entries,
+            metric,
+            color_by_age,
+            descend_archives,
+            follow_symlinks,
             info,
+            roots,
+            scan_extras,
+            focus,
+            focus_info,
+            archives,
+            dirs_by_count,
+            type_newest,
+            timeline_granularity,
+            timeline,
+            dir_tree,
+            dupes,
+            dupe_dirs,
+            dupe_dirs_view,
+            dupes_pending,
+            dupes_errors,
+            junk,
+            junk_pending,
+            cleanup_progress,
+            cleanup_errors,
+            broken_links_confirm,
+            show_errors,
+            elevate_progress,
+            elevate_result,
+            locations,
+            location_sizes,
+            locations_pending,
+            trash_items,
+            trash_selected,
+            trash_confirm,
+            trash_errors,
+            trash_progress,
+            compression,
+            owners,
+            compression_progress,
+            pack_request,
+            pack_remove,
+            pack_progress,
+            pack_result,
+            move_request,
+            move_link,
+            move_progress,
+            move_result,
+            move_browser,
+            dupes_progress,
+            resolve_progress,
             allow_delete,
             filter_chain,
+            recent_paths,
+            bookmarks,
+            files_table_view,
+            dirs_table_view,
+            files_table,
+            dirs_table,
+            path_input,
+            dir_browser,
             dirinfo_receiver,
             dirinfo_sender,
+            archive_receiver,
+            archive_sender,
             ready_receiver,
             ready_sender,
             del_receiver,
             del_sender,
+            dupes_receiver,
+            dupes_sender,
+            resolve_receiver,
+            resolve_sender,
+            locations_receiver,
+            locations_sender,
+            compression_receiver,
+            compression_sender,
+            pack_receiver,
+            pack_sender,
+            move_request_receiver,
+            move_request_sender,
+            move_receiver,
+            move_sender,
+            cleanup_receiver,
+            cleanup_sender,
+            elevate_receiver,
+            elevate_sender,
+            trash_receiver,
+            trash_sender,
+            trash_action_receiver,
+            trash_action_sender,
+            restored_receiver,
+            restored_sender,
             ready,
         } = self;
 
@@ -252,27 +869,343 @@ impl epi::App for MyApp {
 
             // ui.ctx().request_repaint();
 
-            while let Ok(r_info) = dirinfo_receiver.try_recv() {
-                *info = r_info;
+            let mut roots_changed = false;
+            // Set when something was removed, which is applied to the results in place
+            let mut removed = false;
+
+            while let Ok((root_path, r_info, r_extras)) = dirinfo_receiver.try_recv() {
+                if let Some(root) = roots.iter_mut().find(|r| r.path == root_path) {
+                    root.info = r_info;
+                    root.extras = r_extras;
+                    roots_changed = true;
+                }
                 // ui.ctx().request_repaint();
             }
 
-            while let Ok(_) = ready_receiver.try_recv() {
+            while let Ok((root_path, result)) = archive_receiver.try_recv() {
+                if let Some(root) = roots.iter_mut().find(|r| r.path == root_path) {
+                    match result {
+                        Ok(archive) => root.archive = Some(archive),
+                        Err(e) => root.error = Some(format!("Can't read archive: {}", e)),
+                    }
+                    roots_changed = true;
+                }
+            }
+
+            while let Ok(root_path) = ready_receiver.try_recv() {
                 // dbg!("Got RDY");
-                *ready = true;
+                if let Some(root) = roots.iter_mut().find(|r| r.path == root_path) {
+                    root.ready = true;
+                    root.usage = fs_usage(&root.path);
+                }
+                *ready = roots.iter().all(|r| r.ready);
+                roots_changed = true;
+            }
+
+            while let Ok(msg) = pack_receiver.try_recv() {
+                match msg {
+                    PackMessage::Progress { done, total } => {
+                        *pack_progress =
+                            Some(format!("Packed {} of {}", ByteSize(done), ByteSize(total)));
+                    }
+                    PackMessage::Done(Ok(packed)) => {
+                        // The original goes the same way as a deleted directory
+                        if packed.removed {
+                            let _ = del_sender.send(packed.source.clone());
+                        }
+                        for path in &packed.partly_removed {
+                            let _ = del_sender.send(path.clone());
+                        }
+                        let archive = File {
+                            size: packed.archive_size,
+                            ext: Some("zst".to_string()),
+                            path: packed.archive.clone(),
+                            modified: SystemTime::now(),
+                            hash: 0,
+                        };
+                        for root in roots.iter_mut() {
+                            if packed.archive.starts_with(&root.path) {
+                                insert_file(&mut root.info, archive.clone());
+                                update_stats(&root.info, &mut root.extras.stats, &packed.archive);
+                                roots_changed = true;
+                            }
+                        }
+                        *pack_progress = None;
+                        *pack_result = Some(format!(
+                            "Packed into {} ({}){}",
+                            packed.archive.display(),
+                            ByteSize(packed.archive_size),
+                            if packed.removed {
+                                ", original removed".to_string()
+                            } else if let Some(reason) = &packed.kept {
+                                format!(", original kept: {}", reason)
+                            } else {
+                                String::new()
+                            }
+                        ));
+                    }
+                    PackMessage::Done(Err(e)) => {
+                        *pack_progress = None;
+                        *pack_result = Some(format!("Packing failed: {}", e));
+                    }
+                }
+            }
+
+            while let Ok(path) = move_request_receiver.try_recv() {
+                let target = path.parent().unwrap_or(&path).to_string_lossy().to_string();
+                *move_request = Some((path, target));
+                *move_link = false;
+                *move_result = None;
+            }
+            let mut new_links = vec![];
+            while let Ok(msg) = move_receiver.try_recv() {
+                match msg {
+                    MoveMessage::Progress { done, total } => {
+                        *move_progress =
+                            Some(format!("Copied {} of {}", ByteSize(done), ByteSize(total)));
+                    }
+                    MoveMessage::Done(Ok(moved)) => {
+                        // Whatever was known below the old path moves along, if the new
+                        // place is scanned, and is removed from the old place like a deletion
+                        let dirs: Vec<Directory> = roots
+                            .iter()
+                            .flat_map(|r| r.info.tree.values())
+                            .filter(|d| d.path.starts_with(&moved.source))
+                            .cloned()
+                            .collect();
+                        let files: Vec<File> = roots
+                            .iter()
+                            .flat_map(|r| r.info.files.iter())
+                            .filter(|f| f.path.starts_with(&moved.source))
+                            .cloned()
+                            .collect();
+                        for root in roots.iter_mut() {
+                            if moved.destination.starts_with(&root.path) {
+                                graft(
+                                    &mut root.info,
+                                    &moved.source,
+                                    &moved.destination,
+                                    &dirs,
+                                    &files,
+                                );
+                                update_stats(
+                                    &root.info,
+                                    &mut root.extras.stats,
+                                    &moved.destination,
+                                );
+                                roots_changed = true;
+                            }
+                        }
+                        let _ = del_sender.send(moved.source.clone());
+                        // The link takes the place of the directory, after the deletion
+                        if moved.linked {
+                            let link = File {
+                                size: std::fs::symlink_metadata(&moved.source)
+                                    .map(|m| m.len())
+                                    .unwrap_or_default(),
+                                ext: None,
+                                path: moved.source.clone(),
+                                modified: SystemTime::now(),
+                                hash: 0,
+                            };
+                            new_links.push(link);
+                        }
+                        *move_progress = None;
+                        *move_result = Some(match &moved.warning {
+                            Some(warning) => {
+                                format!("Moved to {}. {}", moved.destination.display(), warning)
+                            }
+                            None => format!("Moved to {}", moved.destination.display()),
+                        });
+                    }
+                    MoveMessage::Done(Err(e)) => {
+                        *move_progress = None;
+                        *move_result = Some(format!("Moving failed: {}", e));
+                    }
+                }
+            }
+
+            while let Ok(msg) = cleanup_receiver.try_recv() {
+                match msg {
+                    CleanupMessage::Progress { done, total } => {
+                        *cleanup_progress =
+                            Some(format!("Removed {} of {} directories", done, total));
+                    }
+                    CleanupMessage::Removed(path) => {
+                        let _ = del_sender.send(path);
+                    }
+                    CleanupMessage::Failed(path, e) => {
+                        cleanup_errors.push(format!("{} | {}", path.display(), e));
+                    }
+                    CleanupMessage::Done => *cleanup_progress = None,
+                }
+            }
+
+            while let Ok(msg) = elevate_receiver.try_recv() {
+                match msg {
+                    ElevateMessage::Scanned {
+                        dir,
+                        info: sub,
+                        extras,
+                    } => {
+                        for root in roots.iter_mut() {
+                            if dir.starts_with(&root.path) {
+                                fill_in(root, &dir, &sub, &extras);
+                                roots_changed = true;
+                            }
+                        }
+                        *elevate_progress = Some(format!("Rescanned {}", dir.display()));
+                    }
+                    ElevateMessage::Done(Ok(count)) => {
+                        *elevate_progress = None;
+                        *elevate_result = Some(format!("Rescanned {} directories", count));
+                    }
+                    ElevateMessage::Done(Err(e)) => {
+                        *elevate_progress = None;
+                        *elevate_result = Some(format!("Elevated rescan failed: {}", e));
+                    }
+                }
+            }
+
+            let mut restored = vec![];
+            while let Ok(msg) = trash_action_receiver.try_recv() {
+                match msg {
+                    TrashMessage::Progress { done, total } => {
+                        *trash_progress = Some(format!("Handled {} of {} items", done, total));
+                    }
+                    TrashMessage::Handled {
+                        path,
+                        restored: back,
+                    } => {
+                        if let Some(items) = trash_items {
+                            items.retain(|i| i.path != path);
+                        }
+                        trash_selected.remove(&path);
+                        restored.extend(back);
+                        let _ = del_sender.send(path);
+                    }
+                    TrashMessage::Failed(path, e) => {
+                        trash_errors.push(format!("{} | {}", path.display(), e));
+                    }
+                    TrashMessage::Done => *trash_progress = None,
+                }
+            }
+            // Restored items are scanned again, so they show up in the roots they are in
+            if !restored.is_empty() {
+                scan_restored(restored, *follow_symlinks, restored_sender.clone());
+            }
+
+            while let Ok((path, sub, extras)) = restored_receiver.try_recv() {
+                for root in roots.iter_mut() {
+                    if path.starts_with(&root.path) {
+                        insert_scanned(root, &path, &sub, &extras);
+                        roots_changed = true;
+                    }
+                }
             }
 
             while let Ok(path) = del_receiver.try_recv() {
                 dbg!("Got del", &path);
-                info.tree.remove_entry(&path);
-                info.files.retain(|x| x.path != path);
-                info.dirs_by_size = info.dirs_by_size();
-                info.files_by_size = info.files_by_size();
+                for root in roots.iter_mut() {
+                    // A removed directory takes everything below it along
+                    roots::remove(&mut root.info, &path);
+                    root.extras.remove(&path);
+                    update_stats(&root.info, &mut root.extras.stats, &path);
+                }
+                if roots.len() > 1 {
+                    roots::remove(info, &path);
+                    scan_extras.remove(&path);
+                    update_stats(info, &mut scan_extras.stats, &path);
+                }
+                if let Some(focus_info) = focus_info {
+                    roots::remove(focus_info, &path);
+                }
+                removed = true;
+                // The scan path itself might be gone now
+                path_input.invalidate();
+            }
+            for link in new_links {
+                for root in roots.iter_mut() {
+                    if link.path.starts_with(&root.path) {
+                        insert_file(&mut root.info, link.clone());
+                        update_stats(&root.info, &mut root.extras.stats, &link.path);
+                        if let Some(symlink) = scan::read_symlink(&link.path) {
+                            root.extras.symlinks.insert(link.path.clone(), symlink);
+                        }
+                    }
+                }
+            }
+
+            while let Ok(msg) = dupes_receiver.try_recv() {
+                match msg {
+                    DupeMessage::Progress { stage, done, total } => {
+                        *dupes_progress = Some(format!("{}: {} / {} files", stage, done, total));
+                    }
+                    DupeMessage::Done(groups) => {
+                        *dupes = groups;
+                        *dupes_progress = None;
+                    }
+                }
+            }
+            while let Ok(msg) = resolve_receiver.try_recv() {
+                match msg {
+                    ResolveMessage::Progress { done, total } => {
+                        *resolve_progress = Some(format!("Handled {} of {} copies", done, total));
+                    }
+                    ResolveMessage::Removed(path) => {
+                        forget_copy(dupes, &path);
+                        if let Some(groups) = dupe_dirs {
+                            forget_copy(groups, &path);
+                        }
+                        let _ = del_sender.send(path);
+                    }
+                    ResolveMessage::Linked(path) => {
+                        forget_copy(dupes, &path);
+                    }
+                    ResolveMessage::Failed(path, e) => {
+                        dupes_errors.push(format!("{} | {}", path.display(), e));
+                    }
+                    ResolveMessage::Done => *resolve_progress = None,
+                }
+            }
+            while let Ok((i, size)) = locations_receiver.try_recv() {
+                location_sizes.insert(i, size);
+                *locations_pending = locations_pending.saturating_sub(1);
+            }
+            while let Ok(msg) = compression_receiver.try_recv() {
+                match msg {
+                    CompressionMessage::Progress { done, total } => {
+                        *compression_progress = Some(format!("Sampled {} / {} files", done, total));
+                    }
+                    CompressionMessage::Done(report) => {
+                        *compression = Some(report);
+                        *compression_progress = None;
+                    }
+                }
+            }
+
+            let mut trash_loading = false;
+            if trash_items.is_none() {
+                match trash_receiver.try_recv() {
+                    Ok(items) => *trash_items = Some(items),
+                    Err(_) => trash_loading = true,
+                }
+            }
+            let working = dupes_progress.is_some()
+                || resolve_progress.is_some()
+                || compression_progress.is_some()
+                || pack_progress.is_some()
+                || move_progress.is_some()
+                || cleanup_progress.is_some()
+                || elevate_progress.is_some()
+                || trash_progress.is_some();
+            if working || *locations_pending > 0 || trash_loading {
+                ui.ctx().request_repaint();
             }
 
             ui.set_style(gen_light_style());
             ui.style_mut().visuals.window_corner_radius = 1.;
-            ui.style_mut().visuals.faint_bg_color = Color32::new(100, 0, 100, 255);
+            ui.style_mut().visuals.faint_bg_color = Color32::from_rgb(100, 0, 100);
             ui.style_mut().visuals.widgets.active.corner_radius = 0.;
             //ui.style_mut().interact(ui.ctx().response());
             Window::new("Setup").show(ui.ctx(), |ui| {
@@ -304,44 +1237,229 @@ impl epi::App for MyApp {
                     }
                 });
 
-                ui.text_edit(scan_path);
+                if !bookmarks.is_empty() {
+                    let mut removed_bookmark = None;
+                    ui.collapsing("Bookmarks", |ui| {
+                        for (i, bookmark) in bookmarks.iter().enumerate() {
+                            ui.horizontal(|ui| {
+                                if ui.button("X").clicked() {
+                                    removed_bookmark = Some(i);
+                                }
+                                if ui.button(bookmark).clicked() {
+                                    *scan_path = bookmark.clone();
+                                }
+                            });
+                        }
+                    });
+                    if let Some(i) = removed_bookmark {
+                        bookmarks.remove(i);
+                    }
+                }
+
+                if !recent_paths.is_empty() {
+                    ui.collapsing("Recent", |ui| {
+                        for path in recent_paths.iter() {
+                            if ui.button(path).clicked() {
+                                *scan_path = path.clone();
+                            }
+                        }
+                    });
+                }
+
+                path_input.ui(ui, scan_path);
+                ui.horizontal(|ui| {
+                    if ui.button("Browse...").clicked() {
+                        dir_browser.open_at(scan_path);
+                    }
+                    if !bookmarks.contains(scan_path) && ui.button("Bookmark").clicked() {
+                        bookmarks.push(scan_path.clone());
+                    }
+                });
 
                 // ui.checkbox("Allow deletion", allow_delete);
                 // ui.checkbox(allow_delete, allow_delete);
                 ui.add(Checkbox::new(allow_delete, "Allow deletion"));
+                ui.add(Checkbox::new(descend_archives, "Look inside archives"));
+                ui.add(Checkbox::new(follow_symlinks, "Follow symlinks"));
+                ui.horizontal(|ui| {
+                    ui.add(Checkbox::new(color_by_age, "Color by age"));
+                    if *color_by_age {
+                        draw_age_legend(ui);
+                    }
+                });
 
                 if *ready {
-                    if ui.button("Scan").clicked() {
+                    let scannable = path_input.target(scan_path).is_valid();
+                    let overlap = overlapping_root(roots, scan_path).map(|r| r.to_string());
+                    let mut scan_root = false;
+                    ui.horizontal(|ui| {
+                        if ui.add(Button::new("Scan").enabled(scannable)).clicked() {
+                            roots.clear();
+                            scan_root = true;
+                        }
+                        if ui
+                            .add(Button::new("Add root").enabled(scannable && overlap.is_none()))
+                            .clicked()
+                        {
+                            scan_root = true;
+                        }
+                    });
+                    if let Some(overlap) = overlap {
+                        ui.label(format!("Overlaps with root {}", overlap));
+                    }
+                    if scan_root {
                         *ready = false;
+                        remember_path(recent_paths, scan_path);
+                        roots.push(ScanRoot::new(scan_path));
                         let s = dirinfo_sender.clone();
+                        let a = archive_sender.clone();
                         let r = ready_sender.clone();
-                        get_dirinfo(&scan_path, s, r);
-                        *info = DirInfo::new();
+                        get_dirinfo(scan_path, *descend_archives, *follow_symlinks, s, a, r);
+                        roots_changed = true;
                         // The update loop only happens on repaint, so we need to
                         // make sure we do one next frame
                         ui.ctx().request_repaint();
                     }
                 } else {
-                    ui.label(format!("Scanned {} files...", info.files.len()));
+                    let files: usize = roots.iter().map(|r| r.info.files.len()).sum();
+                    ui.label(format!("Scanned {} files...", files));
+                }
+
+                let errors: usize = roots.iter().map(|r| r.extras.errors.len()).sum();
+                if errors > 0 {
+                    let badge = format!("{} scan errors", errors);
+                    let button = Button::new(badge).text_color(Color32::from_rgb(255, 120, 120));
+                    if ui.add(button).clicked() {
+                        *show_errors = true;
+                    }
+                }
+
+                if let [root] = roots.as_slice() {
+                    draw_archive_summary(ui, root);
+                    if let Some(usage) = &root.usage {
+                        draw_fs_usage(ui, usage);
+                    }
+                }
+
+                if roots.len() > 1 {
+                    let combined: u64 = roots.iter().map(|r| r.info.combined_size).sum();
+                    ui.label(format!("Roots | {} combined", ByteSize(combined)));
+                    let mut removed_root = None;
+                    for (i, root) in roots.iter().enumerate() {
+                        let scale = root.info.combined_size as f32 / combined as f32;
+                        let newest = root
+                            .extras
+                            .stats
+                            .get(Path::new(&root.path))
+                            .map(|s| s.newest);
+                        let color = bar_style.color(newest.unwrap_or(SystemTime::UNIX_EPOCH));
+                        paint_size_bar_before_next(ui, scale, color);
+                        ui.horizontal(|ui| {
+                            if *ready && ui.button("X").clicked() {
+                                removed_root = Some(i);
+                            }
+                            ui.label(format!(
+                                "{} | {} | {} files{}",
+                                root.path,
+                                ByteSize(root.info.combined_size),
+                                root.info.files.len(),
+                                if root.ready { "" } else { " | scanning" }
+                            ));
+                        });
+                        draw_archive_summary(ui, root);
+                        if let Some(usage) = &root.usage {
+                            draw_fs_usage(ui, usage);
+                        }
+                    }
+                    if let Some(i) = removed_root {
+                        roots.remove(i);
+                        roots_changed = true;
+                    }
                 }
             });
 
+            if dir_browser.open {
+                dir_browser.show(ui.ctx(), scan_path);
+            }
+
+            // Several roots are merged once all of them are scanned, not on every update
+            if roots_changed && roots.len() > 1 && *ready {
+                *info = merge_infos(roots.iter().map(|r| &r.info));
+                *scan_extras = ScanExtras::merge(roots.iter().map(|r| &r.extras));
+            } else if roots_changed && roots.len() < 2 {
+                *info = DirInfo::new();
+                *scan_extras = ScanExtras::default();
+            }
+            // A single root is shown from its own results
+            let (info, scan_extras): (&DirInfo, &ScanExtras) = match roots.as_slice() {
+                [root] => (&root.info, &root.extras),
+                _ => (info, scan_extras),
+            };
+
+            if roots_changed || removed {
+                // Drop the focus if its directory is gone
+                if let Some(f) = focus {
+                    if !roots.iter().any(|r| f.starts_with(&r.path)) || !info.tree.contains_key(f) {
+                        *focus = None;
+                    }
+                }
+                // Removals were applied to the focused part already
+                if roots_changed || focus.is_none() {
+                    *focus_info = focus.as_ref().map(|f| subtree_info(info, f));
+                }
+                *archives = archive_roots(info);
+                archives.extend(
+                    roots
+                        .iter()
+                        .filter(|r| r.archive.is_some())
+                        .map(|r| PathBuf::from(&r.path)),
+                );
+                *dirs_by_count = None;
+                *type_newest = None;
+                *timeline = None;
+                *dupe_dirs = None;
+                *junk = None;
+                *compression = None;
+                *owners = None;
+                dir_tree.invalidate();
+                files_table.invalidate();
+                dirs_table.invalidate();
+            }
+
+            // All views below show the focused directory only
+            let view: &DirInfo = focus_info.as_ref().unwrap_or(info);
+            let mut navigate = None;
+            if *metric == Metric::Count && dirs_by_count.is_none() {
+                *dirs_by_count = Some(roots::dirs_by_count(view, &scan_extras.stats));
+            }
+            if bar_style.by_age && type_newest.is_none() {
+                *type_newest = Some(newest_by_type(view));
+            }
+            if timeline.is_none() {
+                *timeline = Some(bucket_files(view, *timeline_granularity));
+            }
+
             Window::new("Filetypes").scroll(true).show(ui.ctx(), |ui| {
-                ui.label(format!("Files by type, largest first"));
-                ui.add(Slider::i32(max_types, 1..=100).text("max results"));
+                ui.label("Files by type, largest first");
+                if let Some(n) = draw_breadcrumbs(ui, roots, focus) {
+                    navigate = Some(n);
+                }
+                ui.add(Slider::new(max_types, 1..=100).text("max results"));
                 //ui.painter().rect_filled(Rect::from_min_max(pos2(0., 0.), pos2(100., 100.)), 2., Srgba::new(255,0,255, 255));
                 // let visuals = ui.style().interact(&response);
 
                 if !*ready {
-                    ui.label(format!("Please wait for scan"));
+                    ui.label("Please wait for scan");
                 }
-                for (i, filetype) in info.types_by_size.iter().enumerate() {
+                for (i, filetype) in view.types_by_size.iter().enumerate() {
                     if i as i32 >= *max_types {
                         break;
                     }
 
-                    let scale = filetype.size as f32 / info.combined_size as f32;
-                    paint_size_bar_before_next(ui, scale, accent_color);
+                    let scale = filetype.size as f32 / view.combined_size as f32;
+                    let newest = type_newest.as_ref().and_then(|t| t.get(&filetype.ext));
+                    let color = bar_style.color(newest.copied().unwrap_or(SystemTime::UNIX_EPOCH));
+                    paint_size_bar_before_next(ui, scale, color);
 
                     ui.collapsing(
                         format!(
@@ -354,83 +1472,1036 @@ impl epi::App for MyApp {
                         |ui| {
                             for file in &filetype.files {
                                 let s = del_sender.clone();
-                                draw_file(ui, file, *allow_delete, s);
+                                let m = move_request_sender.clone();
+                                let link = scan_extras.symlinks.get(&file.path);
+                                draw_file(ui, file, link, *allow_delete, archives, s, m);
                             }
                         },
                     );
                 }
             });
 
-            Window::new("Files").scroll(true).show(ui.ctx(), |ui| {
-                ui.label(format!("Files by size, largest first"));
-                ui.add(Slider::i32(max_files, 1..=100).text("max results"));
+            Window::new("Files").show(ui.ctx(), |ui| {
+                ui.label("Files by size, largest first");
+                if let Some(n) = draw_breadcrumbs(ui, roots, focus) {
+                    navigate = Some(n);
+                }
+                ui.checkbox(files_table_view, "Table");
 
-                for (i, file) in info.files_by_size.iter().enumerate() {
-                    if i as i32 >= *max_files {
-                        break;
-                    }
+                if *files_table_view {
                     let s = del_sender.clone();
-
-                    draw_file(ui, file, *allow_delete, s);
+                    let can_delete = |p: &Path| *allow_delete && !in_archive(p, archives);
+                    files_table.show(
+                        ui,
+                        || file_rows(view, &scan_extras.owners),
+                        can_delete,
+                        bar_style,
+                        s,
+                    );
+                    return;
                 }
+
+                ui.add(Slider::new(max_files, 1..=100).text("max results"));
+                ScrollArea::auto_sized().show(ui, |ui| {
+                    for (i, file) in view.files_by_size.iter().enumerate() {
+                        if i as i32 >= *max_files {
+                            break;
+                        }
+                        let s = del_sender.clone();
+                        let m = move_request_sender.clone();
+                        let link = scan_extras.symlinks.get(&file.path);
+                        draw_file(ui, file, link, *allow_delete, archives, s, m);
+                    }
+                });
             });
 
             Window::new("Largest directories")
                 .scroll(true)
                 .show(ui.ctx(), |ui| {
-                    ui.label(format!("Largest individual directories"));
-                    ui.add(Slider::i32(max_dirs, 1..=100).text("max results"));
+                    ui.label("Largest individual directories");
+                    if let Some(n) = draw_breadcrumbs(ui, roots, focus) {
+                        navigate = Some(n);
+                    }
+                    ui.add(Slider::new(max_dirs, 1..=100).text("max results"));
+                    draw_metric_choice(ui, metric);
+
+                    let ranked: Vec<&Directory> = match metric {
+                        Metric::Size => view.dirs_by_size.iter().collect(),
+                        Metric::Count => dirs_by_count
+                            .iter()
+                            .flatten()
+                            .filter_map(|p| view.tree.get(p))
+                            .collect(),
+                    };
+                    let total_items = (view.files.len() + view.tree.len()) as f32;
 
-                    for (i, dir) in info.dirs_by_size.iter().enumerate() {
+                    for (i, dir) in ranked.into_iter().enumerate() {
                         if i as i32 > *max_dirs {
                             break;
                         }
 
-                        let scale = dir.size as f32 / info.combined_size as f32;
+                        let stat = scan_extras
+                            .stats
+                            .get(&dir.path)
+                            .cloned()
+                            .unwrap_or_default();
+                        let items = stat.items();
+                        let scale = match metric {
+                            Metric::Size => dir.size as f32 / view.combined_size as f32,
+                            Metric::Count => items as f32 / total_items,
+                        };
 
-                        paint_size_bar_before_next(ui, scale, accent_color);
+                        paint_size_bar_before_next(ui, scale, bar_style.color(stat.newest));
 
                         // ui.label(format!("{:?} {}", dir.path, dir.size / 1024 / 1024));
                         ui.collapsing(
                             format!(
-                                "{} | {} | {}%",
+                                "{} | {} | {} items | {}%",
                                 dir.path
                                     .file_name()
                                     .map(|d| d.to_string_lossy().to_string())
                                     .unwrap_or_default(),
                                 ByteSize(dir.size),
+                                items,
                                 (scale * 100.) as u8
                             ),
                             |ui| {
+                                if in_archive(&dir.path, archives) {
+                                    ui.label("Inside an archive");
+                                }
+                                if !in_nested_archive(view, &dir.path, archives)
+                                    && ui.button("Zoom").clicked()
+                                {
+                                    navigate = Some(Some(dir.path.clone()));
+                                }
+                                if !in_archive(&dir.path, archives)
+                                    && !archives.contains(&dir.path)
+                                    && ui.button("Pack").clicked()
+                                {
+                                    let destination = default_destination(&dir.path);
+                                    *pack_request = Some((
+                                        dir.path.clone(),
+                                        destination.to_string_lossy().to_string(),
+                                    ));
+                                    *pack_result = None;
+                                }
+                                if *allow_delete
+                                    && !in_archive(&dir.path, archives)
+                                    && !archives.contains(&dir.path)
+                                    && ui.button("Move to...").clicked()
+                                {
+                                    let _ = move_request_sender.send(dir.path.clone());
+                                }
+                                if *allow_delete
+                                    && !in_archive(&dir.path, archives)
+                                    && !archives.contains(&dir.path)
+                                    && ui.button("Relocate...").clicked()
+                                {
+                                    let parent = dir.path.parent().unwrap_or(&dir.path);
+                                    *move_request = Some((
+                                        dir.path.clone(),
+                                        parent.to_string_lossy().to_string(),
+                                    ));
+                                    *move_link = true;
+                                    *move_result = None;
+                                }
                                 for (i, file) in dir.sorted_files().iter().enumerate() {
                                     if i as i32 > *max_dirs {
                                         break;
                                     }
                                     let s = del_sender.clone();
+                                    let m = move_request_sender.clone();
+                                    let link = scan_extras.symlinks.get(&file.path);
+                                    draw_file(ui, file, link, *allow_delete, archives, s, m);
+                                }
+                            },
+                        );
+                    }
+                });
+
+            Window::new("Directories").show(ui.ctx(), |ui| {
+                ui.label("Directories");
+                if let Some(n) = draw_breadcrumbs(ui, roots, focus) {
+                    navigate = Some(n);
+                }
+
+                ui.checkbox(dirs_table_view, "Table");
 
-                                    draw_file(ui, file, *allow_delete, s);
+                if *dirs_table_view {
+                    let s = del_sender.clone();
+                    let rows = || dir_rows(view, &scan_extras.stats, &scan_extras.owners);
+                    let can_delete = |p: &Path| *allow_delete && !in_archive(p, archives);
+                    dirs_table.show(ui, rows, can_delete, bar_style, s);
+                    return;
+                }
+
+                let top_dirs: Vec<PathBuf> = match focus {
+                    Some(f) => vec![f.clone()],
+                    None => roots.iter().map(|r| PathBuf::from(&r.path)).collect(),
+                };
+                draw_metric_choice(ui, metric);
+                let sender = del_sender.clone();
+                let move_requests = move_request_sender.clone();
+                let action = dir_tree.show(
+                    ui,
+                    view,
+                    &scan_extras.stats,
+                    *metric,
+                    &top_dirs,
+                    archives,
+                    &scan_extras.symlinks,
+                    *allow_delete,
+                    bar_style,
+                    sender,
+                    move_requests,
+                );
+                match action {
+                    Some(TreeAction::Zoom(dir)) => navigate = Some(Some(dir)),
+                    Some(TreeAction::Pack(dir)) => {
+                        let destination = default_destination(&dir);
+                        *pack_request = Some((dir, destination.to_string_lossy().to_string()));
+                        *pack_result = None;
+                    }
+                    Some(TreeAction::Relocate(dir)) => {
+                        let target = dir.parent().unwrap_or(&dir).to_string_lossy().to_string();
+                        *move_request = Some((dir, target));
+                        *move_link = true;
+                        *move_result = None;
+                    }
+                    None => {}
+                }
+            });
+
+            Window::new("Timeline").scroll(true).show(ui.ctx(), |ui| {
+                ui.label("Files by modification date, bytes and file count");
+                if let Some(n) = draw_breadcrumbs(ui, roots, focus) {
+                    navigate = Some(n);
+                }
+                ui.horizontal(|ui| {
+                    let month = ui.radio_value(timeline_granularity, Granularity::Month, "Month");
+                    let year = ui.radio_value(timeline_granularity, Granularity::Year, "Year");
+                    if month.changed() || year.changed() {
+                        *timeline = Some(bucket_files(view, *timeline_granularity));
+                    }
+                });
+                ui.add(Slider::new(max_timeline_files, 1..=100).text("max results"));
+
+                let buckets = timeline.as_ref().map(|t| t.iter()).into_iter().flatten();
+                let max_count = view.files.len().max(1) as f32;
+                for (bucket, bucket_stats) in buckets {
+                    let scale = bucket_stats.bytes as f32 / view.combined_size as f32;
+                    paint_size_bar_before_next(ui, scale, bar_style.color(bucket_stats.newest));
+                    paint_count_bar_before_next(
+                        ui,
+                        bucket_stats.count as f32 / max_count,
+                        ui.visuals().strong_text_color(),
+                    );
+
+                    ui.collapsing(
+                        format!(
+                            "{} | {} | {}% | {} files",
+                            bucket.label(),
+                            ByteSize(bucket_stats.bytes),
+                            (scale * 100.) as u8,
+                            bucket_stats.count
+                        ),
+                        |ui| {
+                            for index in
+                                bucket_stats.files.iter().take(*max_timeline_files as usize)
+                            {
+                                if let Some(file) = view.files.get(*index) {
+                                    let s = del_sender.clone();
+                                    let m = move_request_sender.clone();
+                                    let link = scan_extras.symlinks.get(&file.path);
+                                    draw_file(ui, file, link, *allow_delete, archives, s, m);
+                                }
+                            }
+                        },
+                    );
+                }
+            });
+
+            Window::new("Duplicates").show(ui.ctx(), |ui| {
+                ui.label("Identical files or directories, most reclaimable first");
+                if let Some(n) = draw_breadcrumbs(ui, roots, focus) {
+                    navigate = Some(n);
+                }
+                if ui.checkbox(dupe_dirs_view, "Directories").changed() {
+                    *dupes_pending = None;
+                }
+
+                let groups = if *dupe_dirs_view {
+                    // Directories are compared by the hashes the scan made, so this is quick
+                    if *ready && dupe_dirs.is_none() {
+                        *dupe_dirs = Some(duplicate_dirs(view, archives, &scan_extras.symlinks));
+                    }
+                    match dupe_dirs {
+                        Some(groups) => groups,
+                        None => {
+                            ui.label("Please wait for scan");
+                            return;
+                        }
+                    }
+                } else {
+                    let search =
+                        Button::new("Find duplicates").enabled(*ready && dupes_progress.is_none());
+                    if ui.add(search).clicked() {
+                        *dupes_progress = Some("Starting".to_string());
+                        *dupes_pending = None;
+                        dupes_errors.clear();
+                        dupes.clear();
+                        spawn_search(view, &scan_extras.symlinks, dupes_sender.clone());
+                    }
+                    if let Some(progress) = dupes_progress {
+                        ui.label(progress.as_str());
+                        return;
+                    }
+                    dupes
+                };
+
+                if let Some(progress) = resolve_progress {
+                    ui.label(progress.as_str());
+                    return;
+                }
+
+                if let Some((i, action)) = *dupes_pending {
+                    let group = match groups.get(i) {
+                        Some(group) => group,
+                        None => {
+                            *dupes_pending = None;
+                            return;
+                        }
+                    };
+                    let copies = group.paths.len() - 1;
+                    let question = match action {
+                        DupeAction::Delete => format!(
+                            "Permanently delete {} copies ({})?",
+                            copies,
+                            ByteSize(group.reclaimable())
+                        ),
+                        DupeAction::Trash => format!(
+                            "Move {} copies to trash ({})?",
+                            copies,
+                            ByteSize(group.reclaimable())
+                        ),
+                        DupeAction::HardLink => format!(
+                            "Replace {} copies with hard links to {}?",
+                            copies,
+                            group.paths[0].display()
+                        ),
+                    };
+                    ui.colored_label(Color32::from_rgb(255, 120, 120), question);
+                    let mut confirmed = None;
+                    ui.horizontal(|ui| {
+                        if ui.button("Yes").clicked() {
+                            confirmed = Some(true);
+                        }
+                        if ui.button("Cancel").clicked() {
+                            confirmed = Some(false);
+                        }
+                    });
+                    if confirmed == Some(true) {
+                        dupes_errors.clear();
+                        *resolve_progress = Some("Starting".to_string());
+                        spawn_resolve(group.clone(), action, resolve_sender.clone());
+                    }
+                    if confirmed.is_some() {
+                        *dupes_pending = None;
+                    }
+                    return;
+                }
+
+                let reclaimable: u64 = groups.iter().map(|g| g.reclaimable()).sum();
+                ui.label(format!(
+                    "{} groups, {} reclaimable",
+                    groups.len(),
+                    ByteSize(reclaimable)
+                ));
+                for error in dupes_errors.iter() {
+                    ui.colored_label(Color32::from_rgb(255, 120, 120), error.as_str());
+                }
+                ui.add(Slider::new(max_dupe_groups, 1..=100).text("max results"));
+
+                ScrollArea::auto_sized().show(ui, |ui| {
+                    for (i, group) in groups.iter().enumerate().take(*max_dupe_groups as usize) {
+                        ui.collapsing(
+                            format!(
+                                "{} copies | {} each | {} reclaimable",
+                                group.paths.len(),
+                                ByteSize(group.size),
+                                ByteSize(group.reclaimable())
+                            ),
+                            |ui| {
+                                for (j, path) in group.paths.iter().enumerate() {
+                                    let keep = if j == 0 { "keep" } else { "copy" };
+                                    ui.label(format!("{} {}", keep, path.display()));
                                 }
+                                if !*allow_delete {
+                                    return;
+                                }
+                                ui.horizontal(|ui| {
+                                    if ui.button("Delete copies").clicked() {
+                                        *dupes_pending = Some((i, DupeAction::Delete));
+                                    }
+                                    if ui.button("Trash copies").clicked() {
+                                        *dupes_pending = Some((i, DupeAction::Trash));
+                                    }
+                                    if !*dupe_dirs_view && ui.button("Hard-link copies").clicked() {
+                                        *dupes_pending = Some((i, DupeAction::HardLink));
+                                    }
+                                });
                             },
                         );
                     }
                 });
+            });
 
-            Window::new("Directories")
+            Window::new("Cleanup suggestions").show(ui.ctx(), |ui| {
+                ui.label("Build artifacts and caches that can be regenerated");
+                if let Some(n) = draw_breadcrumbs(ui, roots, focus) {
+                    navigate = Some(n);
+                }
+                if !*ready {
+                    ui.label("Please wait for scan");
+                    return;
+                }
+                if let Some(progress) = cleanup_progress {
+                    ui.label(progress.as_str());
+                    return;
+                }
+                let found =
+                    junk.get_or_insert_with(|| find_junk(view, archives, &scan_extras.symlinks));
+
+                if let Some((removal, paths)) = junk_pending {
+                    let size: u64 = found
+                        .iter()
+                        .filter(|j| paths.contains(&j.path))
+                        .map(|j| j.size)
+                        .sum();
+                    let verb = match removal {
+                        Removal::Trash => "Move to trash",
+                        Removal::Delete => "Permanently delete",
+                    };
+                    ui.colored_label(
+                        Color32::from_rgb(255, 120, 120),
+                        format!("{} {} directories ({})?", verb, paths.len(), ByteSize(size)),
+                    );
+                    let mut confirmed = None;
+                    ui.horizontal(|ui| {
+                        if ui.button("Yes").clicked() {
+                            confirmed = Some(true);
+                        }
+                        if ui.button("Cancel").clicked() {
+                            confirmed = Some(false);
+                        }
+                    });
+                    if confirmed == Some(true) {
+                        cleanup_errors.clear();
+                        *cleanup_progress = Some("Starting".to_string());
+                        spawn_cleanup(*removal, paths.clone(), cleanup_sender.clone());
+                    }
+                    if confirmed.is_some() {
+                        *junk_pending = None;
+                    }
+                    return;
+                }
+
+                let total: u64 = found.iter().map(|j| j.size).sum();
+                ui.label(format!(
+                    "{} directories, {} total",
+                    found.len(),
+                    ByteSize(total)
+                ));
+                for error in cleanup_errors.iter() {
+                    ui.colored_label(Color32::from_rgb(255, 120, 120), error.as_str());
+                }
+                ScrollArea::auto_sized().show(ui, |ui| {
+                    for (kind, (count, size)) in totals_by_kind(found).into_iter().enumerate() {
+                        if count == 0 {
+                            continue;
+                        }
+                        let junk_kind = &JUNK_KINDS[kind];
+                        let paths = || -> Vec<PathBuf> {
+                            found
+                                .iter()
+                                .filter(|j| j.kind == kind)
+                                .map(|j| j.path.clone())
+                                .collect()
+                        };
+                        let scale = size as f32 / view.combined_size as f32;
+                        paint_size_bar_before_next(ui, scale, accent_color);
+                        ui.collapsing(
+                            format!(
+                                "{} ({}) | {} dirs | {}",
+                                junk_kind.dir_name,
+                                junk_kind.description,
+                                count,
+                                ByteSize(size)
+                            ),
+                            |ui| {
+                                ui.horizontal(|ui| {
+                                    if ui.button("Trash all").clicked() {
+                                        *junk_pending = Some((Removal::Trash, paths()));
+                                    }
+                                    if *allow_delete && ui.button("Delete all").clicked() {
+                                        *junk_pending = Some((Removal::Delete, paths()));
+                                    }
+                                });
+                                for j in found.iter().filter(|j| j.kind == kind) {
+                                    ui.horizontal(|ui| {
+                                        ui.label(format!(
+                                            "{} | {}",
+                                            j.path.display(),
+                                            ByteSize(j.size)
+                                        ));
+                                        if ui.button("Trash").clicked() {
+                                            *junk_pending =
+                                                Some((Removal::Trash, vec![j.path.clone()]));
+                                        }
+                                        if *allow_delete && ui.button("Del").clicked() {
+                                            *junk_pending =
+                                                Some((Removal::Delete, vec![j.path.clone()]));
+                                        }
+                                    });
+                                }
+                            },
+                        );
+                    }
+                });
+            });
+
+            Window::new("Broken symlinks").show(ui.ctx(), |ui| {
+                ui.label("Links whose target is gone");
+                if let Some(n) = draw_breadcrumbs(ui, roots, focus) {
+                    navigate = Some(n);
+                }
+                let mut broken: Vec<&Symlink> = scan_extras
+                    .symlinks
+                    .values()
+                    .filter(|l| l.broken)
+                    .filter(|l| {
+                        focus
+                            .as_ref()
+                            .map(|f| l.path.starts_with(f))
+                            .unwrap_or(true)
+                    })
+                    .collect();
+                broken.sort_by(|a, b| a.path.cmp(&b.path));
+                ui.label(format!("{} broken links", broken.len()));
+                if broken.is_empty() {
+                    return;
+                }
+
+                if *broken_links_confirm {
+                    ui.colored_label(
+                        Color32::from_rgb(255, 120, 120),
+                        format!("Remove {} broken links?", broken.len()),
+                    );
+                    let mut confirmed = None;
+                    ui.horizontal(|ui| {
+                        if ui.button("Yes").clicked() {
+                            confirmed = Some(true);
+                        }
+                        if ui.button("Cancel").clicked() {
+                            confirmed = Some(false);
+                        }
+                    });
+                    if confirmed == Some(true) {
+                        for link in &broken {
+                            if std::fs::remove_file(&link.path).is_ok() {
+                                let _ = del_sender.send(link.path.clone());
+                            }
+                        }
+                    }
+                    if confirmed.is_some() {
+                        *broken_links_confirm = false;
+                    }
+                    return;
+                }
+
+                if *allow_delete && ui.button("Remove all").clicked() {
+                    *broken_links_confirm = true;
+                }
+                ScrollArea::auto_sized().show(ui, |ui| {
+                    for link in &broken {
+                        ui.horizontal(|ui| {
+                            if *allow_delete && ui.button("Del").clicked() {
+                                let _ = std::fs::remove_file(&link.path);
+                                let _ = del_sender.send(link.path.clone());
+                            }
+                            ui.label(format!("{}", link.path.display()));
+                            draw_link_target(ui, link);
+                        });
+                    }
+                });
+            });
+
+            Window::new("Errors")
+                .open(show_errors)
+                .show(ui.ctx(), |ui| {
+                    ui.label(format!(
+                    "{} paths could not be read. What is inside unreadable directories is missing \
+                     from the totals, so sizes may be too small",
+                    scan_extras.errors.len()
+                ));
+                    for (kind, count) in scan_extras.errors_by_kind() {
+                        ui.label(format!("{} | {}", count, std::io::Error::from(kind)));
+                    }
+                    for root in roots.iter() {
+                        if let Some(missing) = root.unscanned_bytes() {
+                            ui.label(format!(
+                                "About {} in use on {} was not found by the scan",
+                                ByteSize(missing),
+                                root.path
+                            ));
+                        }
+                    }
+                    let denied = denied_dirs(info, scan_extras);
+                    if let Some(progress) = elevate_progress {
+                        ui.label(progress.as_str());
+                    } else if !denied.is_empty() && *ready {
+                        // Asks for the password through pkexec or sudo, only the helper runs privileged
+                        let label = format!(
+                            "Rescan {} directories with elevated privileges",
+                            denied.len()
+                        );
+                        if ui.button(label).clicked() {
+                            *elevate_result = None;
+                            *elevate_progress = Some("Waiting for authorization".to_string());
+                            spawn_rescan(denied, *follow_symlinks, elevate_sender.clone());
+                        }
+                    }
+                    if let Some(result) = elevate_result {
+                        ui.label(result.as_str());
+                    }
+                    ui.separator();
+                    ScrollArea::auto_sized().show(ui, |ui| {
+                        for error in &scan_extras.errors {
+                            ui.label(format!(
+                                "{} | {}",
+                                error.path.display(),
+                                error.description()
+                            ));
+                        }
+                    });
+                });
+
+            Window::new("Known locations").show(ui.ctx(), |ui| {
+                ui.label("Caches and logs that are usually safe to shrink");
+                let measure = Button::new("Measure").enabled(*locations_pending == 0);
+                if ui.add(measure).clicked() {
+                    location_sizes.clear();
+                    *locations_pending = spawn_measure(locations_sender.clone());
+                }
+                if *locations_pending > 0 {
+                    ui.label(format!("{} locations left to measure", locations_pending));
+                }
+                ui.add(Slider::new(max_location_entries, 1..=100).text("max results"));
+
+                let largest = location_sizes
+                    .values()
+                    .map(|l| l.size)
+                    .max()
+                    .unwrap_or(1)
+                    .max(1);
+                ScrollArea::auto_sized().show(ui, |ui| {
+                    for (i, location) in locations.iter().enumerate() {
+                        let measured = match location_sizes.get(&i) {
+                            Some(measured) => measured,
+                            None => continue,
+                        };
+                        paint_size_bar_before_next(
+                            ui,
+                            measured.size as f32 / largest as f32,
+                            accent_color,
+                        );
+                        ui.collapsing(
+                            format!("{} | {}", location.name, ByteSize(measured.size)),
+                            |ui| {
+                                ui.label(format!("{}", location.path.display()));
+                                ui.label(location.description);
+                                let limit = *max_location_entries as usize;
+                                for (path, size) in measured.entries.iter().take(limit) {
+                                    let name = path
+                                        .file_name()
+                                        .map(|n| n.to_string_lossy().to_string())
+                                        .unwrap_or_default();
+                                    ui.label(format!("{} | {}", name, ByteSize(*size)));
+                                }
+                                if ui.button("Scan this").clicked() {
+                                    *scan_path = location.path.to_string_lossy().to_string();
+                                }
+                            },
+                        );
+                    }
+                });
+            });
+
+            Window::new("Trash").show(ui.ctx(), |ui| {
+                ui.label("Trashed files still use disk space until they are purged");
+                if ui.button("Refresh").clicked() {
+                    *trash_items = None;
+                    trash_selected.clear();
+                    trash::spawn_list(trash_sender.clone());
+                }
+                let items = match trash_items {
+                    Some(items) => items,
+                    None => {
+                        ui.label("Listing trash...");
+                        return;
+                    }
+                };
+                let total: u64 = items.iter().map(|i| i.size).sum();
+                ui.label(format!("{} items, {}", items.len(), ByteSize(total)));
+
+                let selected: Vec<TrashItem> = items
+                    .iter()
+                    .filter(|i| trash_selected.contains(&i.path))
+                    .cloned()
+                    .collect();
+                let idle = trash_progress.is_none();
+                ui.horizontal(|ui| {
+                    if ui.button("Select all").clicked() {
+                        trash_selected.extend(items.iter().map(|i| i.path.clone()));
+                    }
+                    if ui.button("Select none").clicked() {
+                        trash_selected.clear();
+                    }
+                    if ui
+                        .add(Button::new("Restore").enabled(idle && !selected.is_empty()))
+                        .clicked()
+                    {
+                        trash_errors.clear();
+                        *trash_progress = Some("Starting".to_string());
+                        let s = trash_action_sender.clone();
+                        trash::spawn_action(TrashAction::Restore, selected.clone(), s);
+                    }
+                    if ui
+                        .add(Button::new("Purge").enabled(idle && !selected.is_empty()))
+                        .clicked()
+                    {
+                        *trash_confirm = true;
+                    }
+                });
+
+                if *trash_confirm {
+                    let size: u64 = selected.iter().map(|i| i.size).sum();
+                    ui.colored_label(
+                        Color32::from_rgb(255, 120, 120),
+                        format!(
+                            "Permanently delete {} items ({})?",
+                            selected.len(),
+                            ByteSize(size)
+                        ),
+                    );
+                    ui.horizontal(|ui| {
+                        if ui.button("Yes").clicked() {
+                            trash_errors.clear();
+                            *trash_progress = Some("Starting".to_string());
+                            let s = trash_action_sender.clone();
+                            trash::spawn_action(TrashAction::Purge, selected.clone(), s);
+                            *trash_confirm = false;
+                        }
+                        if ui.button("Cancel").clicked() {
+                            *trash_confirm = false;
+                        }
+                    });
+                }
+
+                if let Some(progress) = trash_progress {
+                    ui.label(progress.as_str());
+                }
+                for error in trash_errors.iter() {
+                    ui.colored_label(Color32::from_rgb(255, 120, 120), error.as_str());
+                }
+
+                ScrollArea::auto_sized().show(ui, |ui| {
+                    for item in items.iter() {
+                        ui.horizontal(|ui| {
+                            let mut checked = trash_selected.contains(&item.path);
+                            if ui.checkbox(&mut checked, "").changed() {
+                                if checked {
+                                    trash_selected.insert(item.path.clone());
+                                } else {
+                                    trash_selected.remove(&item.path);
+                                }
+                            }
+                            let original = item
+                                .original
+                                .as_ref()
+                                .unwrap_or(&item.path)
+                                .display()
+                                .to_string();
+                            ui.label(format!(
+                                "{} | {} | deleted {}",
+                                original,
+                                ByteSize(item.size),
+                                item.deleted.as_deref().unwrap_or("?")
+                            ));
+                        });
+                    }
+                });
+            });
+
+            if roots.iter().any(|r| r.archive.is_some()) {
+                Window::new("Archive").show(ui.ctx(), |ui| {
+                    ui.label("Archive entries, largest first");
+                    if let Some(n) = draw_breadcrumbs(ui, roots, focus) {
+                        navigate = Some(n);
+                    }
+                    ui.add(Slider::new(max_archive_entries, 1..=100).text("max results"));
+                    let compressed_size = |path: &PathBuf| {
+                        roots
+                            .iter()
+                            .filter_map(|r| r.archive.as_ref())
+                            .find_map(|a| a.compressed.get(path).copied())
+                    };
+                    ScrollArea::auto_sized().show(ui, |ui| {
+                        let entries = view
+                            .files_by_size
+                            .iter()
+                            .filter_map(|f| compressed_size(&f.path).map(|c| (f, c)));
+                        for (file, compressed) in entries.take(*max_archive_entries as usize) {
+                            ui.label(format!(
+                                "{} | {} | {} packed{}",
+                                file.path
+                                    .file_name()
+                                    .map(|n| n.to_string_lossy().to_string())
+                                    .unwrap_or_default(),
+                                ByteSize(file.size),
+                                ByteSize(compressed),
+                                ratio(file.size, compressed)
+                                    .map(|r| format!(" | ratio {:.1}x", r))
+                                    .unwrap_or_default()
+                            ));
+                        }
+                    });
+                });
+            }
+
+            Window::new("Compression")
                 .scroll(true)
                 .show(ui.ctx(), |ui| {
-                    ui.label(format!("Directories"));
+                    ui.label("Estimated savings from compressing with zstd, based on samples");
+                    if let Some(n) = draw_breadcrumbs(ui, roots, focus) {
+                        navigate = Some(n);
+                    }
+                    let estimate =
+                        Button::new("Estimate").enabled(*ready && compression_progress.is_none());
+                    if ui.add(estimate).clicked() {
+                        *compression_progress = Some("Starting".to_string());
+                        spawn_estimate(view, compression_sender.clone());
+                    }
+                    if let Some(progress) = compression_progress {
+                        ui.label(progress.as_str());
+                        return;
+                    }
+                    let report = match compression {
+                        Some(report) => report,
+                        None => return,
+                    };
+                    ui.add(Slider::new(max_compression_estimates, 1..=100).text("max results"));
+                    let limit = *max_compression_estimates as usize;
+                    let total = view.combined_size.max(1) as f32;
+                    ui.label("By type");
+                    for estimate in report.types.iter().take(limit) {
+                        paint_size_bar_before_next(
+                            ui,
+                            estimate.savings() as f32 / total,
+                            accent_color,
+                        );
+                        ui.label(format!(
+                            "{} would shrink from {} to {}",
+                            estimate.name,
+                            ByteSize(estimate.size),
+                            ByteSize(estimate.compressed)
+                        ));
+                    }
+                    ui.label("By directory");
+                    for estimate in report.dirs.iter().take(limit) {
+                        paint_size_bar_before_next(
+                            ui,
+                            estimate.savings() as f32 / total,
+                            accent_color,
+                        );
+                        ui.label(format!(
+                            "{} would shrink from {} to {}",
+                            dir_label(estimate),
+                            ByteSize(estimate.size),
+                            ByteSize(estimate.compressed)
+                        ))
+                        .on_hover_text(&estimate.name);
+                    }
+                });
+
+            Window::new("Owners").scroll(true).show(ui.ctx(), |ui| {
+                ui.label("Files by owner, largest first");
+                if let Some(n) = draw_breadcrumbs(ui, roots, focus) {
+                    navigate = Some(n);
+                }
+                let report = owners.get_or_insert_with(|| {
+                    owner_report(view, &scan_extras.owners, &user_names(), &group_names())
+                });
+                ui.add(Slider::new(max_owner_files, 1..=100).text("max results"));
+                let total = view.combined_size.max(1) as f32;
+                ui.label("By user");
+                for user in &report.users {
+                    let scale = user.size as f32 / total;
+                    paint_size_bar_before_next(ui, scale, accent_color);
+                    ui.collapsing(
+                        format!(
+                            "{} | {} | {}% | {} files",
+                            user.name,
+                            ByteSize(user.size),
+                            (scale * 100.) as u8,
+                            user.files.len()
+                        ),
+                        |ui| {
+                            for file in user.files.iter().take(*max_owner_files as usize) {
+                                let s = del_sender.clone();
+                                let m = move_request_sender.clone();
+                                let link = scan_extras.symlinks.get(&file.path);
+                                draw_file(ui, file, link, *allow_delete, archives, s, m);
+                            }
+                        },
+                    );
+                }
+                ui.label("By group");
+                for group in &report.groups {
+                    let scale = group.size as f32 / total;
+                    paint_size_bar_before_next(ui, scale, accent_color);
+                    ui.label(format!(
+                        "{} | {} | {}% | {} files",
+                        group.name,
+                        ByteSize(group.size),
+                        (scale * 100.) as u8,
+                        group.files.len()
+                    ));
+                }
+                if report.unknown > 0 {
+                    ui.label(format!(
+                        "{} without a known owner",
+                        ByteSize(report.unknown)
+                    ));
+                }
+            });
+
+            let mut close_pack = false;
+            if let Some((source, destination)) = pack_request {
+                Window::new("Pack directory").show(ui.ctx(), |ui| {
+                    ui.label(format!("Pack {} into a .tar.zst archive", source.display()));
+                    ui.horizontal(|ui| {
+                        ui.label("Archive");
+                        ui.text_edit_singleline(destination);
+                    });
+                    if *allow_delete {
+                        ui.checkbox(
+                            pack_remove,
+                            "Remove the directory once the archive is verified",
+                        );
+                    } else {
+                        ui.label("Allow deletion to remove the directory afterwards");
+                    }
+                    if let Some(progress) = pack_progress {
+                        ui.label(progress.as_str());
+                    } else {
+                        ui.horizontal(|ui| {
+                            let start =
+                                Button::new("Start").enabled(*ready && !destination.is_empty());
+                            if ui.add(start).clicked() {
+                                *pack_progress = Some("Starting".to_string());
+                                *pack_result = None;
+                                spawn_pack(
+                                    source.clone(),
+                                    PathBuf::from(destination.as_str()),
+                                    *pack_remove && *allow_delete,
+                                    pack_sender.clone(),
+                                );
+                            }
+                            if ui.button("Close").clicked() {
+                                close_pack = true;
+                            }
+                        });
+                    }
+                    if let Some(result) = pack_result {
+                        ui.label(result.as_str());
+                    }
+                });
+            }
+            if close_pack {
+                *pack_request = None;
+                *pack_result = None;
+            }
 
-                    let root_dir = PathBuf::from(scan_path.clone());
-                    if let Some(d) = info.tree.get(&root_dir) {
-                        let sender = del_sender.clone();
-                        draw_dir(ui, d, info, allow_delete.clone(), accent_color, sender)
+            let mut close_move = false;
+            if let Some((source, target)) = move_request {
+                let title = if *move_link {
+                    "Relocate and link"
+                } else {
+                    "Move"
+                };
+                Window::new(title).show(ui.ctx(), |ui| {
+                    if *move_link {
+                        ui.label(format!(
+                            "Copy {} into another directory, verify the copy and replace the \
+                             original with a symlink to it. If a step fails, everything stays \
+                             as it was.",
+                            source.display()
+                        ));
+                    } else {
+                        ui.label(format!("Move {} into another directory", source.display()));
+                    }
+                    ui.horizontal(|ui| {
+                        ui.label("Into");
+                        ui.text_edit_singleline(target);
+                        if ui.button("Browse...").clicked() {
+                            move_browser.open_at(target);
+                        }
+                    });
+                    if let Some(progress) = move_progress {
+                        ui.label(progress.as_str());
+                    } else {
+                        ui.horizontal(|ui| {
+                            let valid = Path::new(target.as_str()).is_dir();
+                            if ui
+                                .add(Button::new(title).enabled(*ready && valid))
+                                .clicked()
+                            {
+                                *move_progress = Some("Starting".to_string());
+                                *move_result = None;
+                                let (source, target) =
+                                    (source.clone(), PathBuf::from(target.as_str()));
+                                if *move_link {
+                                    spawn_relocate(source, target, move_sender.clone());
+                                } else {
+                                    spawn_move(source, target, move_sender.clone());
+                                }
+                            }
+                            if ui.button("Close").clicked() {
+                                close_move = true;
+                            }
+                        });
+                    }
+                    if let Some(result) = move_result {
+                        ui.label(result.as_str());
                     }
                 });
+                if move_browser.open {
+                    move_browser.show(ui.ctx(), target);
+                }
+            }
+            if close_move {
+                *move_request = None;
+                *move_result = None;
+            }
 
             Window::new("Filter builder")
                 .scroll(true)
                 .show(ui.ctx(), |ui| {
-                    ui.label(format!("Filtered files"));
+                    ui.label("Filtered files");
+                    if let Some(n) = draw_breadcrumbs(ui, roots, focus) {
+                        navigate = Some(n);
+                    }
 
                     if ui.button("Add min size").clicked() {
                         filter_chain.push(Filter::MinSize(5));
@@ -446,37 +2517,40 @@ impl epi::App for MyApp {
                     }
 
                     // Edit filters
-                    for filter in filter_chain.iter_mut() {
+                    let mut removed_filter = None;
+                    for (filter_index, filter) in filter_chain.iter_mut().enumerate() {
                         match filter {
                             Filter::MinSize(size) => {
                                 ui.add(
-                                    Slider::i32(size, 1..=1000)
+                                    Slider::new(size, 1..=1000)
                                         .text("hide files smaller than this (MB)"),
                                 );
                                 if ui.button("X").clicked() {
-                                    //filter_chain.retain(|x| *x != *filter);
-                                    drop(filter);
+                                    removed_filter = Some(filter_index);
                                 }
                             }
                             Filter::MinAge(age) => {
                                 ui.add(
-                                    Slider::i32(age, 1..=500).text("hide files newer than (days)"),
+                                    Slider::new(age, 1..=500).text("hide files newer than (days)"),
                                 );
                             }
                             Filter::MaxAge(age) => {
                                 ui.add(
-                                    Slider::i32(age, 1..=500).text("hide files older than (days)"),
+                                    Slider::new(age, 1..=500).text("hide files older than (days)"),
                                 );
                             }
                             Filter::MaxResults(max) => {
-                                ui.add(Slider::i32(max, 1..=100).text("max results"));
+                                ui.add(Slider::new(max, 1..=100).text("max results"));
                             } // _ => (),
                         }
                     }
+                    if let Some(filter_index) = removed_filter {
+                        filter_chain.remove(filter_index);
+                    }
 
                     if !filter_chain.is_empty() {
                         let mut i = 0;
-                        'filter: for file in &info.files_by_size {
+                        'filter: for file in &view.files_by_size {
                             for filter in filter_chain.iter() {
                                 match filter {
                                     Filter::MinSize(minsize) => {
@@ -508,41 +2582,60 @@ impl epi::App for MyApp {
                                         // if file.modified.elapsed() {}
                                     }
                                     Filter::MaxResults(max) => {
-                                        if i as i32 >= *max {
+                                        if i >= *max {
                                             break 'filter;
                                         }
                                     } // _ => (),
                                 }
                             }
                             let s = del_sender.clone();
-
-                            draw_file(ui, file, *allow_delete, s);
+                            let m = move_request_sender.clone();
+                            let link = scan_extras.symlinks.get(&file.path);
+                            draw_file(ui, file, link, *allow_delete, archives, s, m);
 
                             i += 1;
                         }
                     }
                 });
+
+            if let Some(new_focus) = navigate {
+                *focus = new_focus;
+                *focus_info = focus.as_ref().map(|f| subtree_info(info, f));
+                *dirs_by_count = None;
+                *type_newest = None;
+                *timeline = None;
+                *dupe_dirs = None;
+                *junk = None;
+                *compression = None;
+                *owners = None;
+                dir_tree.invalidate();
+                files_table.invalidate();
+                dirs_table.invalidate();
+            }
+
             // Default::default()
         });
     }
-
-    // fn on_exit(&mut self, storage: &mut dyn egui::app::Storage) {
-    //     // egui::app::set_value(storage, egui::app::APP_KEY, self);
-    // }
 }
 
 fn main() {
+    let args: Vec<std::ffi::OsString> = std::env::args_os().skip(1).collect();
+    if let Some(code) = elevate::helper_main(&args) {
+        std::process::exit(code);
+    }
+
     // let i = diskspace_insight::scan("/home/woelper/Downloads");
     std::env::set_var("RUST_LOG", "info");
     let _ = env_logger::try_init();
 
-    let title = "birdseye";
-    // let storage = FileStorage::from_path(".birdseye.json".into());
-    let mut app: MyApp = MyApp::default();
-    app.scan_path = dirs::home_dir()
-        .unwrap_or_default()
-        .to_string_lossy()
-        .to_string();
+    // The home dir is the fallback if there are no stored settings yet
+    let app = MyApp {
+        scan_path: dirs::home_dir()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string(),
+        ..Default::default()
+    };
     let native_options = eframe::NativeOptions::default();
 
     eframe::run_native(Box::new(app), native_options);
@@ -0,0 +1,96 @@
+//! Who the scanned data belongs to, totalled per user and per group.
+
+use crate::scan::Owners;
+use diskspace_insight::{DirInfo, File};
+use std::collections::HashMap;
+
+/// The files of one user or group
+#[derive(Debug, Clone)]
+pub struct OwnerTotal {
+    pub id: u32,
+    /// The name from /etc/passwd or /etc/group, or the id if there is none
+    pub name: String,
+    pub size: u64,
+    /// Largest first
+    pub files: Vec<File>,
+}
+
+/// Totals per user and per group, largest first
+#[derive(Debug, Clone, Default)]
+pub struct OwnerReport {
+    pub users: Vec<OwnerTotal>,
+    pub groups: Vec<OwnerTotal>,
+    /// Bytes in files without a known owner, like those inside archives
+    pub unknown: u64,
+}
+
+fn totals(files: HashMap<u32, Vec<File>>, names: &HashMap<u32, String>) -> Vec<OwnerTotal> {
+    let mut totals: Vec<OwnerTotal> = files
+        .into_iter()
+        .map(|(id, mut files)| {
+            files.sort_by_key(|f| std::cmp::Reverse(f.size));
+            OwnerTotal {
+                id,
+                name: names.get(&id).cloned().unwrap_or_else(|| id.to_string()),
+                size: files.iter().map(|f| f.size).sum(),
+                files,
+            }
+        })
+        .collect();
+    totals.sort_by(|a, b| b.size.cmp(&a.size).then(a.id.cmp(&b.id)));
+    totals
+}
+
+/// Total the files of `info` by owner
+pub fn owner_report(
+    info: &DirInfo,
+    owners: &Owners,
+    user_names: &HashMap<u32, String>,
+    group_names: &HashMap<u32, String>,
+) -> OwnerReport {
+    let mut by_user: HashMap<u32, Vec<File>> = HashMap::new();
+    let mut by_group: HashMap<u32, Vec<File>> = HashMap::new();
+    let mut unknown = 0;
+    for file in &info.files {
+        match owners.get(&file.path) {
+            Some(owner) => {
+                by_user.entry(owner.uid).or_default().push(file.clone());
+                by_group.entry(owner.gid).or_default().push(file.clone());
+            }
+            None => unknown += file.size,
+        }
+    }
+    OwnerReport {
+        users: totals(by_user, user_names),
+        groups: totals(by_group, group_names),
+        unknown,
+    }
+}
+
+/// Map uids to user names, from /etc/passwd
+pub fn user_names() -> HashMap<u32, String> {
+    std::fs::read_to_string("/etc/passwd")
+        .map(|passwd| parse_id_names(&passwd))
+        .unwrap_or_default()
+}
+
+/// Map gids to group names, from /etc/group
+pub fn group_names() -> HashMap<u32, String> {
+    std::fs::read_to_string("/etc/group")
+        .map(|group| parse_id_names(&group))
+        .unwrap_or_default()
+}
+
+/// Parse `name:x:id:...` lines, as found in /etc/passwd and /etc/group
+pub fn parse_id_names(content: &str) -> HashMap<u32, String> {
+    content
+        .lines()
+        .filter(|l| !l.starts_with('#'))
+        .filter_map(|l| {
+            let mut fields = l.split(':');
+            let name = fields.next()?;
+            let id = fields.nth(1)?.parse().ok()?;
+            Some((id, name.to_string()))
+        })
+        .collect()
+}
@@ -0,0 +1,422 @@
+//! Packing a directory into a `.tar.zst`, checking the result against the original
+//! and optionally removing the original afterwards.
+
+use crate::archive::scan_archive;
+use crate::compress::ZSTD_LEVEL;
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::hash::Hasher;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::SystemTime;
+use twox_hash::XxHash64;
+
+const TAR_BLOCK: usize = 512;
+/// Progress is reported after every file, and within large files after this many bytes
+const PROGRESS_STEP: u64 = 4 * 1024 * 1024;
+
+/// What the packing thread reports
+pub enum PackMessage {
+    /// Bytes of file content written so far
+    Progress {
+        done: u64,
+        total: u64,
+    },
+    Done(Result<Packed, String>),
+}
+
+/// A finished and verified archive
+#[derive(Debug, Clone)]
+pub struct Packed {
+    pub source: PathBuf,
+    pub archive: PathBuf,
+    pub archive_size: u64,
+    /// Whether the original directory was removed
+    pub removed: bool,
+    /// Why the original was kept, when its removal was asked for
+    pub kept: Option<String>,
+    /// What was removed before removing the rest of the original failed
+    pub partly_removed: Vec<PathBuf>,
+}
+
+/// `dir.tar.zst` next to the directory
+pub fn default_destination(dir: &Path) -> PathBuf {
+    let mut name = dir.file_name().unwrap_or_default().to_owned();
+    name.push(".tar.zst");
+    dir.with_file_name(name)
+}
+
+/// Something to put into the archive, with its name inside
+#[derive(Debug)]
+pub struct Item {
+    path: PathBuf,
+    name: String,
+    meta: fs::Metadata,
+}
+
+/// Whether an item goes into the archive. Sockets, fifos and devices are left out.
+fn packable(meta: &fs::Metadata) -> bool {
+    meta.is_dir() || meta.is_file() || meta.file_type().is_symlink()
+}
+
+/// Names and link targets go into the archive as UTF-8. Others are refused rather than
+/// mangled, as a mangled name would still pass verification.
+fn utf8<'a>(path: &Path, name: &'a OsStr) -> io::Result<&'a str> {
+    name.to_str().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} is not valid UTF-8", path.display()),
+        )
+    })
+}
+
+/// The directory and everything below it, parents before children. Symlinks are not followed.
+fn collect(path: &Path, name: String, items: &mut Vec<Item>) -> io::Result<()> {
+    let meta = fs::symlink_metadata(path)?;
+    let is_dir = meta.is_dir();
+    items.push(Item {
+        path: path.to_path_buf(),
+        name: name.clone(),
+        meta,
+    });
+    if is_dir {
+        let mut entries: Vec<fs::DirEntry> = fs::read_dir(path)?.collect::<io::Result<_>>()?;
+        entries.sort_by_key(|e| e.file_name());
+        for entry in entries {
+            let child = format!("{}/{}", name, utf8(&entry.path(), &entry.file_name())?);
+            collect(&entry.path(), child, items)?;
+        }
+    }
+    Ok(())
+}
+
+/// Write a number as zero-padded octal, or base-256 if it doesn't fit
+fn put_number(field: &mut [u8], value: u64) {
+    let digits = field.len() - 1;
+    if digits < 22 && value >= 1 << (3 * digits) {
+        for (i, byte) in field.iter_mut().rev().enumerate() {
+            *byte = (value.checked_shr(8 * i as u32).unwrap_or_default() & 0xff) as u8;
+        }
+        field[0] |= 0x80;
+        return;
+    }
+    let octal = format!("{:0width$o}", value, width = digits);
+    field[..digits].copy_from_slice(octal.as_bytes());
+    field[digits] = 0;
+}
+
+fn put_bytes(field: &mut [u8], bytes: &[u8]) {
+    let len = bytes.len().min(field.len());
+    field[..len].copy_from_slice(&bytes[..len]);
+}
+
+/// A ustar header block. `owner` is the uid and gid.
+fn header(
+    name: &[u8],
+    size: u64,
+    mode: u32,
+    owner: (u32, u32),
+    mtime: u64,
+    typeflag: u8,
+    link: &[u8],
+) -> Vec<u8> {
+    let mut block = vec![0u8; TAR_BLOCK];
+    put_bytes(&mut block[0..100], name);
+    put_number(&mut block[100..108], mode as u64);
+    put_number(&mut block[108..116], owner.0 as u64);
+    put_number(&mut block[116..124], owner.1 as u64);
+    put_number(&mut block[124..136], size);
+    put_number(&mut block[136..148], mtime);
+    block[156] = typeflag;
+    put_bytes(&mut block[157..257], link);
+    block[257..263].copy_from_slice(b"ustar\0");
+    block[263..265].copy_from_slice(b"00");
+    // The checksum is computed with its own field filled with spaces
+    block[148..156].copy_from_slice(b"        ");
+    let checksum: u64 = block.iter().map(|b| *b as u64).sum();
+    put_number(&mut block[148..155], checksum);
+    block[155] = b' ';
+    block
+}
+
+fn pad(writer: &mut impl Write, size: u64) -> io::Result<()> {
+    let rest = (TAR_BLOCK - (size % TAR_BLOCK as u64) as usize) % TAR_BLOCK;
+    writer.write_all(&vec![0u8; rest])
+}
+
+/// A GNU long name ('L') or long link ('K') entry, for names that don't fit in the header
+fn write_long(writer: &mut impl Write, typeflag: u8, name: &[u8]) -> io::Result<()> {
+    let size = name.len() as u64 + 1;
+    writer.write_all(&header(
+        b"././@LongLink",
+        size,
+        0o644,
+        (0, 0),
+        0,
+        typeflag,
+        b"",
+    ))?;
+    writer.write_all(name)?;
+    writer.write_all(&[0])?;
+    pad(writer, size)
+}
+
+#[cfg(unix)]
+fn mode(meta: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    meta.permissions().mode() & 0o7777
+}
+
+#[cfg(not(unix))]
+fn mode(meta: &fs::Metadata) -> u32 {
+    if meta.is_dir() {
+        0o755
+    } else {
+        0o644
+    }
+}
+
+#[cfg(unix)]
+fn owner(meta: &fs::Metadata) -> (u32, u32) {
+    use std::os::unix::fs::MetadataExt;
+    (meta.uid(), meta.gid())
+}
+
+#[cfg(not(unix))]
+fn owner(_meta: &fs::Metadata) -> (u32, u32) {
+    (0, 0)
+}
+
+/// Write the items as a tarball. Returns the size and content hash of each file, by name.
+fn write_tar(
+    writer: &mut impl Write,
+    items: &[Item],
+    progress: &dyn Fn(u64),
+) -> io::Result<HashMap<String, (u64, u64)>> {
+    let mut written = HashMap::new();
+    let mut done = 0;
+    let mut buf = vec![0u8; 64 * 1024];
+    for item in items {
+        let mtime = item
+            .meta
+            .modified()
+            .ok()
+            .and_then(|m| m.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let (name, typeflag, size, link) = if item.meta.is_dir() {
+            (format!("{}/", item.name), b'5', 0, vec![])
+        } else if item.meta.file_type().is_symlink() {
+            let target = fs::read_link(&item.path)?;
+            let target = utf8(&item.path, target.as_os_str())?.as_bytes().to_vec();
+            (item.name.clone(), b'2', 0, target)
+        } else if item.meta.is_file() {
+            (item.name.clone(), b'0', item.meta.len(), vec![])
+        } else {
+            continue;
+        };
+
+        if link.len() > 100 {
+            write_long(writer, b'K', &link)?;
+        }
+        if name.len() > 100 {
+            write_long(writer, b'L', name.as_bytes())?;
+        }
+        writer.write_all(&header(
+            name.as_bytes(),
+            size,
+            mode(&item.meta),
+            owner(&item.meta),
+            mtime,
+            typeflag,
+            &link,
+        ))?;
+        if typeflag != b'0' {
+            continue;
+        }
+
+        let mut file = fs::File::open(&item.path)?.take(size);
+        let mut hasher = XxHash64::default();
+        let mut copied = 0;
+        loop {
+            let read = file.read(&mut buf)?;
+            if read == 0 {
+                break;
+            }
+            hasher.write(&buf[..read]);
+            writer.write_all(&buf[..read])?;
+            copied += read as u64;
+            done += read as u64;
+            if copied % PROGRESS_STEP < read as u64 {
+                progress(done);
+            }
+        }
+        if copied != size {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("{} changed while packing", item.path.display()),
+            ));
+        }
+        progress(done);
+        pad(writer, size)?;
+        written.insert(name, (size, hasher.finish()));
+    }
+    // The end of the archive is marked by two empty blocks
+    writer.write_all(&[0u8; 2 * TAR_BLOCK])?;
+    Ok(written)
+}
+
+/// Check that the archive lists exactly the files that were written, with the same contents
+fn verify(archive: &Path, written: &HashMap<String, (u64, u64)>) -> io::Result<()> {
+    let (contents, _) = scan_archive(archive)?;
+    let mismatch = |what: String| {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Verification failed: {}", what),
+        ))
+    };
+    if contents.files.len() != written.len() {
+        return mismatch(format!(
+            "{} files written, {} in the archive",
+            written.len(),
+            contents.files.len()
+        ));
+    }
+    for file in &contents.files {
+        let name = file
+            .path
+            .strip_prefix(archive)
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        if written.get(&name) != Some(&(file.size, file.hash)) {
+            return mismatch(format!("{} differs", name));
+        }
+    }
+    Ok(())
+}
+
+/// Pack `source` into a new `.tar.zst` at `destination` and verify it. An existing file at
+/// the destination is never overwritten, and an unfinished archive is removed again.
+/// Returns the size of the archive and the items that were collected for it.
+pub fn pack(
+    source: &Path,
+    destination: &Path,
+    progress: &dyn Fn(u64, u64),
+) -> io::Result<(u64, Vec<Item>)> {
+    let name = source
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Nothing to pack"))?;
+    let name = utf8(source, name)?.to_string();
+    if destination.starts_with(source) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "The archive can't be inside the directory it packs",
+        ));
+    }
+    let mut items = vec![];
+    collect(source, name, &mut items)?;
+    let total = items
+        .iter()
+        .filter(|i| i.meta.is_file())
+        .map(|i| i.meta.len())
+        .sum();
+
+    let file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(destination)?;
+    let result: io::Result<u64> = (|| {
+        let mut encoder = zstd::Encoder::new(io::BufWriter::new(file), ZSTD_LEVEL)?;
+        let written = write_tar(&mut encoder, &items, &|done| progress(done, total))?;
+        encoder.finish()?.flush()?;
+        verify(destination, &written)?;
+        Ok(fs::metadata(destination)?.len())
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(destination);
+    }
+    Ok((result?, items))
+}
+
+/// Whether the tree still looks like it did when the items were collected
+fn unchanged(source: &Path, items: &[Item]) -> io::Result<bool> {
+    let mut now = vec![];
+    collect(source, String::new(), &mut now)?;
+    let same = |a: &fs::Metadata, b: &fs::Metadata| {
+        a.file_type() == b.file_type()
+            && a.len() == b.len()
+            && a.modified().ok() == b.modified().ok()
+    };
+    Ok(now.len() == items.len()
+        && now
+            .iter()
+            .zip(items)
+            .all(|(now, item)| now.path == item.path && same(&now.meta, &item.meta)))
+}
+
+/// Remove the packed items, children before parents. Nothing is removed if something could
+/// not be packed or the tree changed since. What was removed is added to `removed`.
+fn remove_packed(source: &Path, items: &[Item], removed: &mut Vec<PathBuf>) -> io::Result<()> {
+    if let Some(item) = items.iter().find(|i| !packable(&i.meta)) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} is not in the archive", item.path.display()),
+        ));
+    }
+    if !unchanged(source, items)? {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} changed while packing", source.display()),
+        ));
+    }
+    for item in items.iter().rev() {
+        if item.meta.is_dir() {
+            fs::remove_dir(&item.path)?;
+        } else {
+            fs::remove_file(&item.path)?;
+        }
+        removed.push(item.path.clone());
+    }
+    Ok(())
+}
+
+/// Pack a directory in the background, removing it afterwards if `remove_original` is set
+pub fn spawn_pack(
+    source: PathBuf,
+    destination: PathBuf,
+    remove_original: bool,
+    sender: Sender<PackMessage>,
+) {
+    thread::spawn(move || {
+        let progress = |done, total| {
+            let _ = sender.send(PackMessage::Progress { done, total });
+        };
+        let result = pack(&source, &destination, &progress)
+            .map(|(archive_size, items)| {
+                let mut packed = Packed {
+                    source: source.clone(),
+                    archive: destination.clone(),
+                    archive_size,
+                    removed: false,
+                    kept: None,
+                    partly_removed: vec![],
+                };
+                if remove_original {
+                    let mut removed = vec![];
+                    match remove_packed(&source, &items, &mut removed) {
+                        Ok(()) => packed.removed = true,
+                        Err(e) => {
+                            packed.kept = Some(e.to_string());
+                            packed.partly_removed = removed;
+                        }
+                    }
+                }
+                packed
+            })
+            .map_err(|e| e.to_string());
+        let _ = sender.send(PackMessage::Done(result));
+    });
+}
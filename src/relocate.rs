@@ -0,0 +1,284 @@
+//! Moving files and directories to another place, also across filesystems.
+
+use crate::dupes::full_hash;
+use crate::locations::disk_usage;
+use std::fs;
+use std::hash::Hasher;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::thread;
+use twox_hash::XxHash64;
+
+/// Progress is reported after every file, and within large files after this many bytes
+const PROGRESS_STEP: u64 = 4 * 1024 * 1024;
+
+/// What the moving thread reports
+pub enum MoveMessage {
+    /// Bytes copied so far. Moves within a filesystem finish without progress.
+    Progress {
+        done: u64,
+        total: u64,
+    },
+    Done(Result<Moved, String>),
+}
+
+/// A finished move
+#[derive(Debug, Clone)]
+pub struct Moved {
+    pub source: PathBuf,
+    pub destination: PathBuf,
+    /// Whether `source` is now a symlink to `destination`
+    pub linked: bool,
+    /// Something that went wrong after the move itself succeeded
+    pub warning: Option<String>,
+}
+
+/// Remove a file, symlink or directory
+fn remove(path: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(path)?.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+/// Copy a file to a new path and read the copy back to make sure it arrived intact
+fn copy_file(
+    source: &Path,
+    destination: &Path,
+    done: &mut u64,
+    progress: &dyn Fn(u64),
+) -> io::Result<()> {
+    let meta = fs::metadata(source)?;
+    let mut reader = fs::File::open(source)?;
+    let mut writer = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(destination)?;
+    let mut hasher = XxHash64::default();
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut copied = 0;
+    loop {
+        let read = reader.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        hasher.write(&buf[..read]);
+        writer.write_all(&buf[..read])?;
+        copied += read as u64;
+        *done += read as u64;
+        if copied % PROGRESS_STEP < read as u64 {
+            progress(*done);
+        }
+    }
+    progress(*done);
+    writer.sync_all()?;
+    writer.set_permissions(meta.permissions())?;
+    if let Ok(modified) = meta.modified() {
+        let _ = writer.set_modified(modified);
+    }
+    if full_hash(destination)? != hasher.finish() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("The copy of {} differs", source.display()),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn copy_symlink(source: &Path, destination: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(fs::read_link(source)?, destination)
+}
+
+#[cfg(not(unix))]
+fn copy_symlink(source: &Path, _destination: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("Can't copy the symlink {}", source.display()),
+    ))
+}
+
+/// Copy a file or directory with everything below it, verifying each file.
+/// Symlinks are copied as links. `done` counts the bytes copied.
+pub fn copy_tree(
+    source: &Path,
+    destination: &Path,
+    done: &mut u64,
+    progress: &dyn Fn(u64),
+) -> io::Result<()> {
+    let meta = fs::symlink_metadata(source)?;
+    if meta.file_type().is_symlink() {
+        copy_symlink(source, destination)
+    } else if meta.is_dir() {
+        fs::create_dir(destination)?;
+        for entry in fs::read_dir(source)? {
+            let entry = entry?;
+            copy_tree(
+                &entry.path(),
+                &destination.join(entry.file_name()),
+                done,
+                progress,
+            )?;
+        }
+        fs::set_permissions(destination, meta.permissions())
+    } else if meta.is_file() {
+        copy_file(source, destination, done, progress)
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not a regular file", source.display()),
+        ))
+    }
+}
+
+/// Where `source` ends up when moved into `target_dir`, refusing to overwrite anything
+fn destination_in(source: &Path, target_dir: &Path) -> io::Result<PathBuf> {
+    let name = source
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Nothing to move"))?;
+    let destination = target_dir.join(name);
+    if destination.starts_with(source) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Can't move a directory into itself",
+        ));
+    }
+    if fs::symlink_metadata(&destination).is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} exists", destination.display()),
+        ));
+    }
+    Ok(destination)
+}
+
+/// Copy `source` to `destination`, removing the partial copy again if anything fails
+fn copy_verified(source: &Path, destination: &Path, progress: &dyn Fn(u64, u64)) -> io::Result<()> {
+    let total = disk_usage(source);
+    let mut done = 0;
+    copy_tree(source, destination, &mut done, &|done| {
+        progress(done, total)
+    })
+    .inspect_err(|_| {
+        let _ = remove(destination);
+    })
+}
+
+/// Move a file or directory into `target_dir`. Within a filesystem it is renamed,
+/// otherwise copied, verified and then removed. Returns the new path.
+pub fn move_into(
+    source: &Path,
+    target_dir: &Path,
+    progress: &dyn Fn(u64, u64),
+) -> io::Result<PathBuf> {
+    let destination = destination_in(source, target_dir)?;
+    match fs::rename(source, &destination) {
+        Ok(()) => Ok(destination),
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            copy_verified(source, &destination, progress)?;
+            remove(source)?;
+            Ok(destination)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Move a file or directory in the background
+pub fn spawn_move(source: PathBuf, target_dir: PathBuf, sender: Sender<MoveMessage>) {
+    thread::spawn(move || {
+        let progress = |done, total| {
+            let _ = sender.send(MoveMessage::Progress { done, total });
+        };
+        let result = move_into(&source, &target_dir, &progress)
+            .map(|destination| Moved {
+                source: source.clone(),
+                destination,
+                linked: false,
+                warning: None,
+            })
+            .map_err(|e| e.to_string());
+        let _ = sender.send(MoveMessage::Done(result));
+    });
+}
+
+#[cfg(unix)]
+fn symlink_dir(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(not(unix))]
+fn symlink_dir(_target: &Path, _link: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Symlinks are only supported on unix",
+    ))
+}
+
+/// Copy a directory into `target_dir`, verify the copy and replace the original with a
+/// symlink to it. Until the link is in place, every failure restores the previous state.
+/// Returns the path of the copy, and a warning if the original could not be removed afterwards.
+pub fn relocate_and_link(
+    source: &Path,
+    target_dir: &Path,
+    progress: &dyn Fn(u64, u64),
+) -> io::Result<(PathBuf, Option<String>)> {
+    if !fs::symlink_metadata(source)?.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Only directories can be relocated",
+        ));
+    }
+    let destination = destination_in(source, target_dir)?;
+    // A link to a relative path would point somewhere else
+    let destination = if destination.is_absolute() {
+        destination
+    } else {
+        std::env::current_dir()?.join(destination)
+    };
+    // The original is kept aside until the link works
+    let mut backup = source.as_os_str().to_owned();
+    backup.push(".birdseye-old");
+    let backup = PathBuf::from(backup);
+    if fs::symlink_metadata(&backup).is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} exists", backup.display()),
+        ));
+    }
+
+    copy_verified(source, &destination, progress)?;
+    if let Err(e) = fs::rename(source, &backup) {
+        let _ = remove(&destination);
+        return Err(e);
+    }
+    if let Err(e) = symlink_dir(&destination, source) {
+        let _ = fs::rename(&backup, source);
+        let _ = remove(&destination);
+        return Err(e);
+    }
+    // The link works, so leftovers of the original are no reason to fail
+    let warning = fs::remove_dir_all(&backup)
+        .err()
+        .map(|e| format!("The original is left at {}: {}", backup.display(), e));
+    Ok((destination, warning))
+}
+
+/// Relocate a directory and link it in the background
+pub fn spawn_relocate(source: PathBuf, target_dir: PathBuf, sender: Sender<MoveMessage>) {
+    thread::spawn(move || {
+        let progress = |done, total| {
+            let _ = sender.send(MoveMessage::Progress { done, total });
+        };
+        let result = relocate_and_link(&source, &target_dir, &progress)
+            .map(|(destination, warning)| Moved {
+                source: source.clone(),
+                destination,
+                linked: true,
+                warning,
+            })
+            .map_err(|e| e.to_string());
+        let _ = sender.send(MoveMessage::Done(result));
+    });
+}
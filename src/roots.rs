@@ -0,0 +1,372 @@
+//! Several scan roots in one session, merged into a combined view.
+
+use crate::archive::ArchiveInfo;
+use crate::fsinfo::FsUsage;
+use crate::scan::{rank, DirStats, ScanExtras};
+use diskspace_insight::{DirInfo, Directory, File, FileType};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// A path that was scanned, with its own results
+pub struct ScanRoot {
+    pub path: String,
+    pub info: DirInfo,
+    pub ready: bool,
+    /// Capacity of the filesystem the root is on, read once the scan is done
+    pub usage: Option<FsUsage>,
+    /// Set if the root is an archive whose contents were listed
+    pub archive: Option<ArchiveInfo>,
+    /// Why the root could not be read, if it failed as a whole
+    pub error: Option<String>,
+    /// What the scan found besides `info`
+    pub extras: ScanExtras,
+}
+
+impl ScanRoot {
+    /// Roughly how much of the filesystem the scan did not find, for roots at its top.
+    /// Files that could not be read are the usual reason, but block sizes add a little too.
+    pub fn unscanned_bytes(&self) -> Option<u64> {
+        self.usage
+            .filter(|u| u.mount_point && self.archive.is_none())
+            .map(|u| u.used_bytes.saturating_sub(self.info.combined_size))
+    }
+
+    pub fn new(path: &str) -> ScanRoot {
+        ScanRoot {
+            path: path.to_string(),
+            info: DirInfo::new(),
+            ready: false,
+            usage: None,
+            archive: None,
+            error: None,
+            extras: ScanExtras::default(),
+        }
+    }
+}
+
+/// Merge the results of several roots into one DirInfo, as if they had been scanned together.
+/// `duplicates` is left empty, as the hashes are only comparable within one scan.
+pub fn merge_infos<'a>(infos: impl IntoIterator<Item = &'a DirInfo>) -> DirInfo {
+    let mut merged = DirInfo::new();
+    for info in infos {
+        merged.combined_size += info.combined_size;
+        merged.files.extend(info.files.iter().cloned());
+        merged
+            .tree
+            .extend(info.tree.iter().map(|(p, d)| (p.clone(), d.clone())));
+        for (ext, filetype) in &info.filetypes {
+            let merged_type = merged.filetypes.entry(ext.clone()).or_insert(FileType {
+                ext: ext.clone(),
+                size: 0,
+                files: vec![],
+            });
+            merged_type.size += filetype.size;
+            merged_type.files.extend(filetype.files.iter().cloned());
+        }
+    }
+    rank(&mut merged);
+    merged
+}
+
+/// The part of a DirInfo below `dir`, so that views can be scoped without rescanning.
+pub fn subtree_info(info: &DirInfo, dir: &Path) -> DirInfo {
+    let mut sub = DirInfo::new();
+    sub.files = info
+        .files
+        .iter()
+        .filter(|f| f.path.starts_with(dir))
+        .cloned()
+        .collect();
+    sub.tree = info
+        .tree
+        .iter()
+        .filter(|(p, _)| p.starts_with(dir))
+        .map(|(p, d)| (p.clone(), d.clone()))
+        .collect();
+    for file in &sub.files {
+        sub.combined_size += file.size;
+        if let Some(ext) = &file.ext {
+            let filetype = sub.filetypes.entry(ext.clone()).or_insert(FileType {
+                ext: ext.clone(),
+                size: 0,
+                files: vec![],
+            });
+            filetype.size += file.size;
+            filetype.files.push(file.clone());
+        }
+    }
+    rank(&mut sub);
+    sub
+}
+
+/// Forget a path that was removed from disk, with everything below it. Sizes and rankings
+/// are updated in place, so that nothing needs to be merged or sorted again.
+pub fn remove(info: &mut DirInfo, path: &Path) {
+    let removed: u64 = info
+        .files
+        .iter()
+        .filter(|f| f.path.starts_with(path))
+        .map(|f| f.size)
+        .sum();
+    info.tree.retain(|p, _| !p.starts_with(path));
+    info.files.retain(|f| !f.path.starts_with(path));
+    info.files_by_size.retain(|f| !f.path.starts_with(path));
+    info.dirs_by_size.retain(|d| !d.path.starts_with(path));
+    for filetype in info.filetypes.values_mut() {
+        filetype.files.retain(|f| !f.path.starts_with(path));
+        filetype.size = filetype.files.iter().map(|f| f.size).sum();
+    }
+    info.filetypes.retain(|_, t| !t.files.is_empty());
+    for filetype in info.types_by_size.iter_mut() {
+        filetype.files.retain(|f| !f.path.starts_with(path));
+        filetype.size = filetype.files.iter().map(|f| f.size).sum();
+    }
+    info.types_by_size.retain(|t| !t.files.is_empty());
+    info.types_by_size
+        .sort_by_key(|t| std::cmp::Reverse(t.size));
+    info.combined_size = info.combined_size.saturating_sub(removed);
+
+    if let Some(parent) = path.parent().and_then(|p| info.tree.get_mut(p)) {
+        parent.directories.retain(|d| d != path);
+        let size: u64 = parent
+            .files
+            .iter()
+            .filter(|f| f.path == path)
+            .map(|f| f.size)
+            .sum();
+        parent.files.retain(|f| f.path != path);
+        parent.size = parent.size.saturating_sub(size);
+    }
+    for ancestor in path.ancestors().skip(1) {
+        match info.tree.get_mut(ancestor) {
+            Some(d) => d.combined_size = d.combined_size.saturating_sub(removed),
+            None => break,
+        }
+    }
+    // The ranking holds copies of the directories
+    for dir in info.dirs_by_size.iter_mut() {
+        if path.starts_with(&dir.path) {
+            if let Some(d) = info.tree.get(&dir.path) {
+                *dir = d.clone();
+            }
+        }
+    }
+    info.dirs_by_size.sort_by_key(|d| std::cmp::Reverse(d.size));
+}
+
+fn add_to_type(info: &mut DirInfo, file: &File) {
+    if let Some(ext) = &file.ext {
+        let filetype = info.filetypes.entry(ext.clone()).or_insert(FileType {
+            ext: ext.clone(),
+            size: 0,
+            files: vec![],
+        });
+        filetype.size += file.size;
+        filetype.files.push(file.clone());
+    }
+}
+
+/// Add a file that was created after the scan, if its directory is part of the tree
+pub fn insert_file(info: &mut DirInfo, file: File) {
+    let parent = match file.path.parent() {
+        Some(parent) if info.tree.contains_key(parent) => parent.to_path_buf(),
+        _ => return,
+    };
+    for dir in parent.ancestors() {
+        match info.tree.get_mut(dir) {
+            Some(d) => d.combined_size += file.size,
+            None => break,
+        }
+    }
+    if let Some(dir) = info.tree.get_mut(&parent) {
+        dir.size += file.size;
+        dir.files.push(file.clone());
+    }
+    add_to_type(info, &file);
+    info.combined_size += file.size;
+    info.files.push(file);
+    rank(info);
+}
+
+/// Add files and directories that were moved from `from` to `to` on disk, as listed in the
+/// tree before the move. Nothing happens unless the new parent directory is part of the tree.
+pub fn graft(info: &mut DirInfo, from: &Path, to: &Path, dirs: &[Directory], files: &[File]) {
+    let parent = match to.parent() {
+        Some(parent) if info.tree.contains_key(parent) => parent.to_path_buf(),
+        _ => return,
+    };
+    let rebase = |path: &Path| match path.strip_prefix(from) {
+        Ok(rest) if rest.as_os_str().is_empty() => to.to_path_buf(),
+        Ok(rest) => to.join(rest),
+        Err(_) => path.to_path_buf(),
+    };
+    let moved_file = |file: &File| File {
+        path: rebase(&file.path),
+        ..file.clone()
+    };
+    if dirs.is_empty() {
+        for file in files {
+            insert_file(info, moved_file(file));
+        }
+        return;
+    }
+
+    for dir in dirs {
+        let path = rebase(&dir.path);
+        let moved = Directory {
+            parent: path.parent().map(|p| p.to_path_buf()),
+            directories: dir.directories.iter().map(|d| rebase(d)).collect(),
+            files: dir.files.iter().map(moved_file).collect(),
+            path: path.clone(),
+            ..dir.clone()
+        };
+        info.tree.insert(path, moved);
+    }
+    let size: u64 = files.iter().map(|f| f.size).sum();
+    if let Some(dir) = info.tree.get_mut(&parent) {
+        dir.directories.push(to.to_path_buf());
+    }
+    for dir in parent.ancestors() {
+        match info.tree.get_mut(dir) {
+            Some(d) => d.combined_size += size,
+            None => break,
+        }
+    }
+    info.combined_size += size;
+    for file in files.iter().map(moved_file) {
+        add_to_type(info, &file);
+        info.files.push(file);
+    }
+    rank(info);
+}
+
+/// Put the results of scanning `dir` on its own in place of what the root knew below it,
+/// as after rescanning a directory that could not be read. `dir` must be part of the tree.
+pub fn fill_in(root: &mut ScanRoot, dir: &Path, sub: &DirInfo, sub_extras: &ScanExtras) {
+    let info = &mut root.info;
+    let old_size = match info.tree.get(dir) {
+        Some(d) if sub.tree.contains_key(dir) => d.combined_size,
+        _ => return,
+    };
+    info.tree.retain(|p, _| !p.starts_with(dir));
+    info.files.retain(|f| !f.path.starts_with(dir));
+    for filetype in info.filetypes.values_mut() {
+        filetype.files.retain(|f| !f.path.starts_with(dir));
+        filetype.size = filetype.files.iter().map(|f| f.size).sum();
+    }
+    for ancestor in dir.ancestors().skip(1) {
+        match info.tree.get_mut(ancestor) {
+            Some(d) => d.combined_size = d.combined_size - old_size + sub.combined_size,
+            None => break,
+        }
+    }
+    info.combined_size = info.combined_size - old_size + sub.combined_size;
+
+    info.tree
+        .extend(sub.tree.iter().map(|(p, d)| (p.clone(), d.clone())));
+    info.files.extend(sub.files.iter().cloned());
+    for (ext, filetype) in &sub.filetypes {
+        let known = info.filetypes.entry(ext.clone()).or_insert(FileType {
+            ext: ext.clone(),
+            size: 0,
+            files: vec![],
+        });
+        known.size += filetype.size;
+        known.files.extend(filetype.files.iter().cloned());
+    }
+    rank(info);
+
+    root.extras.remove(dir);
+    root.extras.extend(sub_extras);
+    update_stats(&root.info, &mut root.extras.stats, dir);
+}
+
+/// Add a path that appeared after the scan, like an item restored from the trash, from a
+/// scan of the path alone. Nothing happens unless its parent directory is part of the tree.
+pub fn insert_scanned(root: &mut ScanRoot, path: &Path, sub: &DirInfo, sub_extras: &ScanExtras) {
+    match path.parent() {
+        Some(parent) if root.info.tree.contains_key(parent) => {}
+        _ => return,
+    }
+    let dirs: Vec<Directory> = sub
+        .tree
+        .values()
+        .filter(|d| d.path.starts_with(path))
+        .cloned()
+        .collect();
+    graft(&mut root.info, path, path, &dirs, &sub.files);
+    root.extras.extend(sub_extras);
+    update_stats(&root.info, &mut root.extras.stats, path);
+}
+
+/// What directories are ranked by
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub enum Metric {
+    Size,
+    /// Number of files and subdirectories, which is what uses up inodes
+    Count,
+}
+
+/// Recompute the stats of a directory from its own files and the stats of its subdirectories.
+/// Archive contents hang below the archive file, so they count up to the archive only.
+fn restat(info: &DirInfo, stats: &mut HashMap<PathBuf, DirStats>, dir: &Path) {
+    let d = match info.tree.get(dir) {
+        Some(d) => d,
+        None => {
+            stats.remove(dir);
+            return;
+        }
+    };
+    let mut stat = DirStats {
+        file_count: d.files.len() as u64,
+        ..Default::default()
+    };
+    for file in &d.files {
+        stat.newest = stat.newest.max(file.modified);
+    }
+    for sub in d.directories.iter().filter(|s| info.tree.contains_key(*s)) {
+        stat.dir_count += 1;
+        if let Some(sub) = stats.get(sub) {
+            stat.file_count += sub.file_count;
+            stat.dir_count += sub.dir_count;
+            stat.newest = stat.newest.max(sub.newest);
+        }
+    }
+    stats.insert(dir.to_path_buf(), stat);
+}
+
+/// Bring the stats up to date after something at `path` was added, replaced or removed:
+/// the directories at and below it are recomputed deepest first, then those above it.
+pub fn update_stats(info: &DirInfo, stats: &mut HashMap<PathBuf, DirStats>, path: &Path) {
+    let mut below: Vec<&PathBuf> = info.tree.keys().filter(|p| p.starts_with(path)).collect();
+    below.sort_by_key(|p| std::cmp::Reverse(p.components().count()));
+    for dir in below {
+        restat(info, stats, dir);
+    }
+    for dir in path.ancestors().skip(1) {
+        if !info.tree.contains_key(dir) {
+            break;
+        }
+        restat(info, stats, dir);
+    }
+}
+
+/// All directories, ranked by the number of files and directories anywhere below them
+pub fn dirs_by_count(info: &DirInfo, stats: &HashMap<PathBuf, DirStats>) -> Vec<PathBuf> {
+    let mut dirs: Vec<&Directory> = info.tree.values().collect();
+    dirs.sort_by_key(|d| {
+        std::cmp::Reverse(stats.get(&d.path).map(|s| s.items()).unwrap_or_default())
+    });
+    dirs.into_iter().map(|d| d.path.clone()).collect()
+}
+
+/// The existing root a new path would overlap with, if any.
+/// Overlapping roots would count the same files twice.
+pub fn overlapping_root<'a>(roots: &'a [ScanRoot], path: &str) -> Option<&'a str> {
+    let path = Path::new(path);
+    roots
+        .iter()
+        .map(|r| r.path.as_str())
+        .find(|r| path.starts_with(r) || Path::new(r).starts_with(path))
+}
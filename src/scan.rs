@@ -0,0 +1,430 @@
+//! Walking a directory into a DirInfo, like `diskspace_insight::scan_callback`, but with
+//! symlinks recorded for what they are instead of being taken for plain files.
+
+use crate::dupes::full_hash;
+use diskspace_insight::{DirInfo, Directory, File, FileType};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime};
+use walkdir::WalkDir;
+
+/// A symbolic link met during a scan
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symlink {
+    pub path: PathBuf,
+    /// Where the link points, as stored in the link
+    pub target: PathBuf,
+    /// Whether the target is missing
+    pub broken: bool,
+}
+
+/// Who a file belongs to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Owner {
+    pub uid: u32,
+    pub gid: u32,
+}
+
+#[cfg(unix)]
+fn owner(meta: &fs::Metadata) -> Option<Owner> {
+    use std::os::unix::fs::MetadataExt;
+    Some(Owner {
+        uid: meta.uid(),
+        gid: meta.gid(),
+    })
+}
+
+#[cfg(not(unix))]
+fn owner(_meta: &fs::Metadata) -> Option<Owner> {
+    None
+}
+
+/// Owners of scanned directories and files. Files are only kept on their own if they belong
+/// to someone else than their directory, so this stays small for large trees.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Owners {
+    dirs: HashMap<PathBuf, Owner>,
+    /// Files whose owner differs from their directory's
+    files: HashMap<PathBuf, Owner>,
+}
+
+impl Owners {
+    /// Note the owner of a directory, before the files in it
+    pub fn add_dir(&mut self, path: &Path, owner: Owner) {
+        self.dirs.insert(path.to_path_buf(), owner);
+    }
+
+    pub fn add_file(&mut self, path: &Path, owner: Owner) {
+        if path.parent().and_then(|p| self.dirs.get(p)) != Some(&owner) {
+            self.files.insert(path.to_path_buf(), owner);
+        }
+    }
+
+    /// The owner of a directory or file, if it is known
+    pub fn get(&self, path: &Path) -> Option<Owner> {
+        self.files
+            .get(path)
+            .or_else(|| self.dirs.get(path))
+            .or_else(|| path.parent().and_then(|p| self.dirs.get(p)))
+            .copied()
+    }
+
+    /// Everything that is stored, directories first
+    pub fn iter(&self) -> impl Iterator<Item = (&PathBuf, &Owner)> {
+        self.dirs.iter().chain(self.files.iter())
+    }
+
+    pub fn extend(&mut self, other: &Owners) {
+        self.dirs
+            .extend(other.dirs.iter().map(|(p, o)| (p.clone(), *o)));
+        self.files
+            .extend(other.files.iter().map(|(p, o)| (p.clone(), *o)));
+    }
+
+    /// Forget everything at or below a path
+    pub fn remove(&mut self, path: &Path) {
+        self.dirs.retain(|p, _| !p.starts_with(path));
+        self.files.retain(|p, _| !p.starts_with(path));
+    }
+}
+
+/// A path the scan could not read. Whatever is below it is missing from the results.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScanError {
+    pub path: PathBuf,
+    pub kind: io::ErrorKind,
+}
+
+impl ScanError {
+    fn new(path: &Path, error: &walkdir::Error) -> ScanError {
+        ScanError {
+            path: path.to_path_buf(),
+            kind: error
+                .io_error()
+                .map(|e| e.kind())
+                .unwrap_or(io::ErrorKind::Other),
+        }
+    }
+
+    /// What went wrong, like "permission denied"
+    pub fn description(&self) -> String {
+        io::Error::from(self.kind).to_string()
+    }
+}
+
+/// Recursive numbers of a directory that the DirInfo does not carry
+#[derive(Debug, Clone, PartialEq)]
+pub struct DirStats {
+    pub file_count: u64,
+    pub dir_count: u64,
+    /// Modification time of the newest file
+    pub newest: SystemTime,
+}
+
+impl Default for DirStats {
+    fn default() -> Self {
+        DirStats {
+            file_count: 0,
+            dir_count: 0,
+            newest: SystemTime::UNIX_EPOCH,
+        }
+    }
+}
+
+impl DirStats {
+    /// Files and directories below this one
+    pub fn items(&self) -> u64 {
+        self.file_count + self.dir_count
+    }
+}
+
+/// What a scan finds besides the DirInfo
+#[derive(Debug, Clone, Default)]
+pub struct ScanExtras {
+    /// Symlinks by their path
+    pub symlinks: HashMap<PathBuf, Symlink>,
+    pub errors: Vec<ScanError>,
+    /// Owners of directories and files, where the system has them
+    pub owners: Owners,
+    /// Recursive counts and the newest modification of every directory
+    pub stats: HashMap<PathBuf, DirStats>,
+}
+
+impl ScanExtras {
+    /// Combine the extras of several roots
+    pub fn merge<'a>(all: impl IntoIterator<Item = &'a ScanExtras>) -> ScanExtras {
+        let mut merged = ScanExtras::default();
+        for extras in all {
+            merged.extend(extras);
+        }
+        merged
+    }
+
+    /// Add what another scan found
+    pub fn extend(&mut self, other: &ScanExtras) {
+        self.symlinks
+            .extend(other.symlinks.iter().map(|(p, l)| (p.clone(), l.clone())));
+        self.errors.extend(other.errors.iter().cloned());
+        self.owners.extend(&other.owners);
+        self.stats
+            .extend(other.stats.iter().map(|(p, s)| (p.clone(), s.clone())));
+    }
+
+    /// Number of errors of each kind, most frequent first
+    pub fn errors_by_kind(&self) -> Vec<(io::ErrorKind, usize)> {
+        let mut counts: HashMap<io::ErrorKind, usize> = HashMap::new();
+        for error in &self.errors {
+            *counts.entry(error.kind).or_default() += 1;
+        }
+        let mut counts: Vec<(io::ErrorKind, usize)> = counts.into_iter().collect();
+        counts.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        counts
+    }
+
+    /// Forget everything at or below a path that was removed. The stats of the directories
+    /// above it are left for `roots::update_stats`.
+    pub fn remove(&mut self, path: &Path) {
+        self.symlinks.retain(|p, _| !p.starts_with(path));
+        self.errors.retain(|e| !e.path.starts_with(path));
+        self.owners.remove(path);
+        self.stats.retain(|p, _| !p.starts_with(path));
+    }
+}
+
+/// Whether a path is one of the symlinks, or was reached through one
+pub fn through_symlink(path: &Path, symlinks: &HashMap<PathBuf, Symlink>) -> bool {
+    !symlinks.is_empty() && path.ancestors().any(|a| symlinks.contains_key(a))
+}
+
+/// The link at `path`, if it is one
+pub fn read_symlink(path: &Path) -> Option<Symlink> {
+    let target = fs::read_link(path).ok()?;
+    Some(Symlink {
+        path: path.to_path_buf(),
+        target,
+        broken: fs::metadata(path).is_err(),
+    })
+}
+
+/// Builds up a DirInfo entry by entry
+pub struct Scan {
+    root: PathBuf,
+    info: DirInfo,
+    extras: ScanExtras,
+}
+
+impl Scan {
+    pub fn new(root: &Path) -> Scan {
+        Scan {
+            root: root.to_path_buf(),
+            info: DirInfo::new(),
+            extras: ScanExtras::default(),
+        }
+    }
+
+    pub fn add_dir(&mut self, path: &Path) {
+        if self.info.tree.contains_key(path) {
+            return;
+        }
+        let parent = path.parent().map(|p| p.to_path_buf());
+        if path != self.root {
+            if let Some(parent) = parent.as_ref().and_then(|p| self.info.tree.get_mut(p)) {
+                parent.directories.push(path.to_path_buf());
+            }
+        }
+        self.info.tree.insert(
+            path.to_path_buf(),
+            Directory {
+                path: path.to_path_buf(),
+                parent,
+                ..Default::default()
+            },
+        );
+        self.extras
+            .stats
+            .insert(path.to_path_buf(), DirStats::default());
+        for dir in path.ancestors().skip(1) {
+            match self.extras.stats.get_mut(dir) {
+                Some(stat) => stat.dir_count += 1,
+                None => break,
+            }
+        }
+    }
+
+    pub fn add_file(&mut self, file: File) {
+        if let Some(parent) = file.path.parent() {
+            self.add_dir(parent);
+            if let Some(dir) = self.info.tree.get_mut(parent) {
+                dir.files.push(file.clone());
+                dir.size += file.size;
+            }
+            for dir in parent.ancestors() {
+                match self.info.tree.get_mut(dir) {
+                    Some(dir) if dir.path.starts_with(&self.root) => dir.combined_size += file.size,
+                    _ => break,
+                }
+            }
+            for dir in parent.ancestors() {
+                match self.extras.stats.get_mut(dir) {
+                    Some(stat) => {
+                        stat.file_count += 1;
+                        stat.newest = stat.newest.max(file.modified);
+                    }
+                    None => break,
+                }
+            }
+        }
+        if let Some(ext) = &file.ext {
+            let filetype = self.info.filetypes.entry(ext.clone()).or_insert(FileType {
+                ext: ext.clone(),
+                size: 0,
+                files: vec![],
+            });
+            filetype.size += file.size;
+            filetype.files.push(file.clone());
+        }
+        self.info.combined_size += file.size;
+        self.info.files.push(file);
+    }
+
+    /// A link that is listed like a file of its own small size
+    fn add_link(&mut self, link: Symlink) {
+        if let Ok(meta) = fs::symlink_metadata(&link.path) {
+            self.add_file(File {
+                size: meta.len(),
+                ext: extension(&link.path),
+                path: link.path.clone(),
+                modified: meta.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+                hash: 0,
+            });
+            if let Some(owner) = owner(&meta) {
+                self.add_owner(&link.path, owner);
+            }
+        }
+        self.extras.symlinks.insert(link.path.clone(), link);
+    }
+
+    /// Note a link whose entry is already listed as a directory or file
+    pub fn add_symlink(&mut self, link: Symlink) {
+        self.extras.symlinks.insert(link.path.clone(), link);
+    }
+
+    /// Note who a directory or file that was added already belongs to
+    pub fn add_owner(&mut self, path: &Path, owner: Owner) {
+        if self.info.tree.contains_key(path) {
+            self.extras.owners.add_dir(path, owner);
+        } else {
+            self.extras.owners.add_file(path, owner);
+        }
+    }
+
+    pub fn add_error(&mut self, error: ScanError) {
+        self.extras.errors.push(error);
+    }
+
+    /// The results, with the rankings sorted
+    pub fn finish(self) -> (DirInfo, ScanExtras) {
+        let mut info = self.info;
+        rank(&mut info);
+        (info, self.extras)
+    }
+}
+
+/// Sort the files, types and directories of a DirInfo by size, as the views list them
+pub fn rank(info: &mut DirInfo) {
+    info.files_by_size = info.files_by_size();
+    info.types_by_size = info.types_by_size();
+    info.dirs_by_size = info.dirs_by_size();
+}
+
+pub fn extension(path: &Path) -> Option<String> {
+    path.extension()
+        .map(|e| e.to_string_lossy().to_string().to_lowercase())
+}
+
+/// Scan a directory, calling `callback` with the results so far every `update_rate_ms`.
+/// Symlinks are listed but not followed, unless `follow_symlinks` is set. Followed links
+/// that lead back to one of their ancestors are listed without descending into them.
+/// Without `hash_files` no file is read and all hashes are 0.
+pub fn scan(
+    root: &Path,
+    follow_symlinks: bool,
+    hash_files: bool,
+    callback: impl Fn(&DirInfo, &ScanExtras),
+    update_rate_ms: u128,
+) -> (DirInfo, ScanExtras) {
+    let mut scan = Scan::new(root);
+    let mut timer = Instant::now();
+
+    for entry in WalkDir::new(root).follow_links(follow_symlinks) {
+        match entry {
+            Ok(entry) => {
+                let path = entry.path();
+                let link = if entry.path_is_symlink() {
+                    read_symlink(path)
+                } else {
+                    None
+                };
+                if entry.file_type().is_dir() {
+                    scan.add_dir(path);
+                    if let Some(owner) = entry.metadata().ok().as_ref().and_then(owner) {
+                        scan.add_owner(path, owner);
+                    }
+                    if let Some(link) = link {
+                        scan.add_symlink(link);
+                    }
+                } else if follow_symlinks || link.is_none() {
+                    match entry.metadata() {
+                        Ok(meta) => {
+                            let hash = match hash_files.then(|| full_hash(path)) {
+                                Some(Ok(hash)) => hash,
+                                // The size is known, but the content can't be compared
+                                Some(Err(e)) => {
+                                    scan.add_error(ScanError {
+                                        path: path.to_path_buf(),
+                                        kind: e.kind(),
+                                    });
+                                    0
+                                }
+                                None => 0,
+                            };
+                            scan.add_file(File {
+                                size: meta.len(),
+                                ext: extension(path),
+                                path: path.to_path_buf(),
+                                modified: meta.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+                                hash,
+                            });
+                            if let Some(owner) = owner(&meta) {
+                                scan.add_owner(path, owner);
+                            }
+                        }
+                        Err(e) => scan.add_error(ScanError::new(path, &e)),
+                    }
+                    if let Some(link) = link {
+                        scan.add_symlink(link);
+                    }
+                } else if let Some(link) = link {
+                    scan.add_link(link);
+                }
+            }
+            Err(e) => {
+                // Following a broken link or a loop fails, the link itself is still there
+                if let Some(link) = e.path().and_then(read_symlink) {
+                    scan.add_link(link);
+                } else if let Some(path) = e.path() {
+                    scan.add_error(ScanError::new(path, &e));
+                }
+            }
+        }
+
+        if timer.elapsed().as_millis() > update_rate_ms {
+            callback(&scan.info, &scan.extras);
+            timer = Instant::now();
+        }
+    }
+
+    scan.finish()
+}
@@ -0,0 +1,240 @@
+//! A plain text form of scan results, so they can be passed between processes.
+//!
+//! Each scanned directory is written as a block of tab separated lines:
+//!
+//! ```text
+//! birdseye-snapshot 1
+//! R <root>
+//! D <path>
+//! F <size> <modified> <hash> <path>
+//! L <broken> <path> <target>
+//! O <uid> <gid> <path>
+//! E <error kind> <path>
+//! .
+//! ```
+//!
+//! Directories come before what is inside them. `modified` is in seconds since the epoch.
+//! Files have an `O` line of their own only if their owner differs from their directory's.
+//! Backslashes, tabs and newlines in paths are escaped with a backslash.
+
+use crate::scan::{extension, Owner, Scan, ScanError, ScanExtras, Symlink};
+use diskspace_insight::{DirInfo, File};
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+const HEADER: &str = "birdseye-snapshot 1";
+
+/// Error kinds that survive the trip, everything else arrives as `Other`
+const ERROR_KINDS: [io::ErrorKind; 7] = [
+    io::ErrorKind::PermissionDenied,
+    io::ErrorKind::NotFound,
+    io::ErrorKind::InvalidData,
+    io::ErrorKind::InvalidInput,
+    io::ErrorKind::Interrupted,
+    io::ErrorKind::TimedOut,
+    io::ErrorKind::UnexpectedEof,
+];
+
+#[cfg(unix)]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    path.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    path.to_string_lossy().as_bytes().to_vec()
+}
+
+#[cfg(unix)]
+fn bytes_path(bytes: Vec<u8>) -> PathBuf {
+    use std::os::unix::ffi::OsStringExt;
+    PathBuf::from(std::ffi::OsString::from_vec(bytes))
+}
+
+#[cfg(not(unix))]
+fn bytes_path(bytes: Vec<u8>) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(&bytes).to_string())
+}
+
+fn escape(path: &Path) -> Vec<u8> {
+    let mut escaped = vec![];
+    for byte in path_bytes(path) {
+        match byte {
+            b'\\' => escaped.extend_from_slice(b"\\\\"),
+            b'\t' => escaped.extend_from_slice(b"\\t"),
+            b'\n' => escaped.extend_from_slice(b"\\n"),
+            _ => escaped.push(byte),
+        }
+    }
+    escaped
+}
+
+fn unescape(field: &[u8]) -> PathBuf {
+    let mut bytes = vec![];
+    let mut escaped = false;
+    for &byte in field {
+        if escaped {
+            bytes.push(match byte {
+                b't' => b'\t',
+                b'n' => b'\n',
+                _ => byte,
+            });
+            escaped = false;
+        } else if byte == b'\\' {
+            escaped = true;
+        } else {
+            bytes.push(byte);
+        }
+    }
+    bytes_path(bytes)
+}
+
+/// Write a line of fields, the last of which are paths
+fn write_line(writer: &mut impl Write, fields: &[&str], paths: &[&Path]) -> io::Result<()> {
+    let mut line = fields.join("\t").into_bytes();
+    for path in paths {
+        line.push(b'\t');
+        line.extend(escape(path));
+    }
+    line.push(b'\n');
+    writer.write_all(&line)
+}
+
+/// Write the results of scanning `root`
+pub fn write(
+    writer: &mut impl Write,
+    root: &Path,
+    info: &DirInfo,
+    extras: &ScanExtras,
+) -> io::Result<()> {
+    writeln!(writer, "{}", HEADER)?;
+    write_line(writer, &["R"], &[root])?;
+    let mut dirs: Vec<&PathBuf> = info.tree.keys().collect();
+    dirs.sort();
+    for dir in dirs {
+        write_line(writer, &["D"], &[dir])?;
+    }
+    for file in &info.files {
+        let modified = file
+            .modified
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let fields = [
+            "F",
+            &file.size.to_string(),
+            &modified.to_string(),
+            &file.hash.to_string(),
+        ];
+        write_line(writer, &fields, &[&file.path])?;
+    }
+    for link in extras.symlinks.values() {
+        let broken = if link.broken { "1" } else { "0" };
+        write_line(writer, &["L", broken], &[&link.path, &link.target])?;
+    }
+    for (path, owner) in extras.owners.iter() {
+        let fields = ["O", &owner.uid.to_string(), &owner.gid.to_string()];
+        write_line(writer, &fields, &[path])?;
+    }
+    for error in &extras.errors {
+        write_line(writer, &["E", &format!("{:?}", error.kind)], &[&error.path])?;
+    }
+    writeln!(writer, ".")?;
+    writer.flush()
+}
+
+fn invalid(what: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, what)
+}
+
+fn number<T: std::str::FromStr>(field: &[u8]) -> io::Result<T> {
+    String::from_utf8_lossy(field)
+        .parse()
+        .map_err(|_| invalid(format!("Not a number: {}", String::from_utf8_lossy(field))))
+}
+
+/// Read the next scanned directory, or `None` at the end of the input
+pub fn read(reader: &mut impl BufRead) -> io::Result<Option<(PathBuf, DirInfo, ScanExtras)>> {
+    let mut line = vec![];
+    let mut next_line = |line: &mut Vec<u8>| -> io::Result<bool> {
+        line.clear();
+        if reader.read_until(b'\n', line)? == 0 {
+            return Ok(false);
+        }
+        if line.last() == Some(&b'\n') {
+            line.pop();
+        }
+        Ok(true)
+    };
+
+    if !next_line(&mut line)? {
+        return Ok(None);
+    }
+    if line != HEADER.as_bytes() {
+        return Err(invalid(format!(
+            "Not a snapshot: {}",
+            String::from_utf8_lossy(&line)
+        )));
+    }
+    if !next_line(&mut line)? || !line.starts_with(b"R\t") {
+        return Err(invalid("The snapshot has no root".to_string()));
+    }
+    let root = unescape(&line[2..]);
+    let mut scan = Scan::new(&root);
+
+    loop {
+        if !next_line(&mut line)? {
+            return Err(invalid("The snapshot ends early".to_string()));
+        }
+        if line == b"." {
+            break;
+        }
+        let fields: Vec<&[u8]> = line.split(|b| *b == b'\t').collect();
+        match fields.as_slice() {
+            [b"D", path] => scan.add_dir(&unescape(path)),
+            [b"F", size, modified, hash, path] => {
+                let path = unescape(path);
+                scan.add_file(File {
+                    size: number(size)?,
+                    ext: extension(&path),
+                    path,
+                    modified: SystemTime::UNIX_EPOCH + Duration::from_secs(number(modified)?),
+                    hash: number(hash)?,
+                })
+            }
+            [b"L", broken, path, target] => scan.add_symlink(Symlink {
+                path: unescape(path),
+                target: unescape(target),
+                broken: *broken == b"1",
+            }),
+            [b"O", uid, gid, path] => {
+                let owner = Owner {
+                    uid: number(uid)?,
+                    gid: number(gid)?,
+                };
+                scan.add_owner(&unescape(path), owner)
+            }
+            [b"E", kind, path] => {
+                let kind = String::from_utf8_lossy(kind);
+                scan.add_error(ScanError {
+                    path: unescape(path),
+                    kind: ERROR_KINDS
+                        .iter()
+                        .copied()
+                        .find(|k| format!("{:?}", k) == kind)
+                        .unwrap_or(io::ErrorKind::Other),
+                })
+            }
+            _ => {
+                return Err(invalid(format!(
+                    "Unknown line: {}",
+                    String::from_utf8_lossy(&line)
+                )))
+            }
+        }
+    }
+    let (info, extras) = scan.finish();
+    Ok(Some((root, info, extras)))
+}
@@ -0,0 +1,354 @@
+//! A sortable table with resizable columns, for files and directories.
+
+use crate::age::BarStyle;
+use crate::cleanup::Removals;
+use crate::owners::user_names;
+use crate::scan::{DirStats, Owners};
+use bytesize::ByteSize;
+use diskspace_insight::DirInfo;
+use eframe::egui::{self, Align2, Rect, ScrollArea, Sense, TextStyle, Ui};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::time::SystemTime;
+
+const MIN_COLUMN_WIDTH: f32 = 30.;
+/// Width of the drag handle between two column headers
+const HANDLE_WIDTH: f32 = 6.;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub enum Column {
+    Name,
+    Size,
+    Percent,
+    Count,
+    Modified,
+    Extension,
+    Owner,
+}
+
+impl Column {
+    const ALL: [Column; 7] = [
+        Column::Name,
+        Column::Size,
+        Column::Percent,
+        Column::Count,
+        Column::Modified,
+        Column::Extension,
+        Column::Owner,
+    ];
+
+    fn title(&self) -> &'static str {
+        match self {
+            Column::Name => "Name",
+            Column::Size => "Size",
+            Column::Percent => "% of parent",
+            Column::Count => "Items",
+            Column::Modified => "Modified",
+            Column::Extension => "Type",
+            Column::Owner => "Owner",
+        }
+    }
+
+    fn default_width(&self) -> f32 {
+        match self {
+            Column::Name => 250.,
+            Column::Modified => 110.,
+            _ => 70.,
+        }
+    }
+}
+
+/// One file or directory in a table
+#[derive(Debug, Clone)]
+pub struct TableRow {
+    pub path: PathBuf,
+    pub name: String,
+    pub size: u64,
+    /// Share of the parent directory, from 0 to 1
+    pub percent: f32,
+    pub count: u64,
+    pub modified: SystemTime,
+    pub ext: String,
+    pub owner: Option<u32>,
+    pub is_dir: bool,
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| path.to_string_lossy().to_string())
+}
+
+fn parent_size(info: &DirInfo, path: &Path) -> u64 {
+    path.parent()
+        .and_then(|p| info.tree.get(p))
+        .map(|d| d.combined_size)
+        .unwrap_or(info.combined_size)
+}
+
+fn share(size: u64, total: u64) -> f32 {
+    if total == 0 {
+        0.
+    } else {
+        size as f32 / total as f32
+    }
+}
+
+/// Rows for all files of a DirInfo
+pub fn file_rows(info: &DirInfo, owners: &Owners) -> Vec<TableRow> {
+    info.files
+        .iter()
+        .map(|f| TableRow {
+            path: f.path.clone(),
+            name: file_name(&f.path),
+            size: f.size,
+            percent: share(f.size, parent_size(info, &f.path)),
+            count: 1,
+            modified: f.modified,
+            ext: f.ext.clone().unwrap_or_default(),
+            owner: owners.get(&f.path).map(|o| o.uid),
+            is_dir: false,
+        })
+        .collect()
+}
+
+/// Rows for all directories of a DirInfo. Counts and modification times are recursive.
+pub fn dir_rows(
+    info: &DirInfo,
+    stats: &HashMap<PathBuf, DirStats>,
+    owners: &Owners,
+) -> Vec<TableRow> {
+    info.tree
+        .values()
+        .map(|d| {
+            let stat = stats.get(&d.path).cloned().unwrap_or_default();
+            TableRow {
+                path: d.path.clone(),
+                name: file_name(&d.path),
+                size: d.combined_size,
+                percent: share(d.combined_size, parent_size(info, &d.path)),
+                count: stat.items(),
+                modified: stat.newest,
+                ext: String::new(),
+                owner: owners.get(&d.path).map(|o| o.uid),
+                is_dir: true,
+            }
+        })
+        .collect()
+}
+
+/// How long ago something happened, roughly
+pub fn format_age(time: SystemTime) -> String {
+    let secs = match time.elapsed() {
+        Ok(elapsed) => elapsed.as_secs(),
+        Err(_) => return "in the future".to_string(),
+    };
+    let (amount, unit) = match secs {
+        s if s < 3600 => (s / 60, "minute"),
+        s if s < 86400 => (s / 3600, "hour"),
+        s if s < 86400 * 30 => (s / 86400, "day"),
+        s if s < 86400 * 365 => (s / (86400 * 30), "month"),
+        s => (s / (86400 * 365), "year"),
+    };
+    format!(
+        "{} {}{} ago",
+        amount,
+        unit,
+        if amount == 1 { "" } else { "s" }
+    )
+}
+
+/// Column layout and sorting of a table. These are persisted, the rows are not.
+#[derive(Deserialize, Serialize)]
+#[serde(default)]
+pub struct Table {
+    widths: Vec<f32>,
+    sort_column: Column,
+    descending: bool,
+    #[serde(skip)]
+    rows: Option<Vec<TableRow>>,
+    #[serde(skip)]
+    user_names: Option<HashMap<u32, String>>,
+    /// Deletions started from the table
+    #[serde(skip)]
+    removals: Removals,
+}
+
+impl Default for Table {
+    fn default() -> Self {
+        Table {
+            widths: Column::ALL.iter().map(|c| c.default_width()).collect(),
+            sort_column: Column::Size,
+            descending: true,
+            rows: None,
+            user_names: None,
+            removals: Removals::default(),
+        }
+    }
+}
+
+impl Table {
+    /// Drop the rows, so they are rebuilt on the next frame
+    pub fn invalidate(&mut self) {
+        self.rows = None;
+    }
+
+    fn owner_name(&self, owner: Option<u32>) -> String {
+        match owner {
+            Some(uid) => self
+                .user_names
+                .as_ref()
+                .and_then(|n| n.get(&uid).cloned())
+                .unwrap_or_else(|| uid.to_string()),
+            None => "-".to_string(),
+        }
+    }
+
+    fn sort(&self, rows: &mut [TableRow]) {
+        match self.sort_column {
+            Column::Name => rows.sort_by_cached_key(|r| r.name.to_lowercase()),
+            Column::Size => rows.sort_by_key(|r| r.size),
+            Column::Percent => rows.sort_by(|a, b| {
+                a.percent
+                    .partial_cmp(&b.percent)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            }),
+            Column::Count => rows.sort_by_key(|r| r.count),
+            Column::Modified => rows.sort_by_key(|r| r.modified),
+            Column::Extension => rows.sort_by(|a, b| a.ext.cmp(&b.ext)),
+            Column::Owner => rows.sort_by_cached_key(|r| self.owner_name(r.owner)),
+        }
+        if self.descending {
+            rows.reverse();
+        }
+    }
+
+    fn cell_text(&self, row: &TableRow, column: Column) -> String {
+        match column {
+            Column::Name => row.name.clone(),
+            Column::Size => format!("{}", ByteSize(row.size)),
+            Column::Percent => format!("{:.1}%", row.percent * 100.),
+            Column::Count => row.count.to_string(),
+            Column::Modified => format_age(row.modified),
+            Column::Extension => row.ext.clone(),
+            Column::Owner => self.owner_name(row.owner),
+        }
+    }
+
+    /// Draw the table. `make_rows` is only called when the rows need to be rebuilt.
+    /// Rows get a Del button if `can_delete` allows it.
+    pub fn show(
+        &mut self,
+        ui: &mut Ui,
+        make_rows: impl FnOnce() -> Vec<TableRow>,
+        can_delete: impl Fn(&Path) -> bool,
+        bar_style: BarStyle,
+        del_sender: Sender<PathBuf>,
+    ) {
+        if self.user_names.is_none() {
+            self.user_names = Some(user_names());
+        }
+        self.removals.show(ui, &del_sender);
+        self.widths.resize(Column::ALL.len(), MIN_COLUMN_WIDTH);
+        if self.rows.is_none() {
+            let mut rows = make_rows();
+            self.sort(&mut rows);
+            self.rows = Some(rows);
+        }
+
+        let row_height = ui.spacing().interact_size.y;
+        let text_color = ui.visuals().text_color();
+        let mut resort = false;
+
+        // Header, with a drag handle to the right of each column
+        ui.horizontal(|ui| {
+            ui.spacing_mut().item_spacing.x = 0.;
+            for (i, column) in Column::ALL.iter().enumerate() {
+                let (rect, response) =
+                    ui.allocate_exact_size(egui::vec2(self.widths[i], row_height), Sense::click());
+                let arrow = match (self.sort_column == *column, self.descending) {
+                    (true, true) => " v",
+                    (true, false) => " ^",
+                    _ => "",
+                };
+                ui.painter().sub_region(rect).text(
+                    rect.left_center(),
+                    Align2::LEFT_CENTER,
+                    format!("{}{}", column.title(), arrow),
+                    TextStyle::Button,
+                    ui.visuals().strong_text_color(),
+                );
+                if response.clicked() {
+                    if self.sort_column == *column {
+                        self.descending = !self.descending;
+                    } else {
+                        // Numbers start with the largest, text alphabetically
+                        self.sort_column = *column;
+                        self.descending =
+                            !matches!(column, Column::Name | Column::Extension | Column::Owner);
+                    }
+                    resort = true;
+                }
+
+                let (handle, response) =
+                    ui.allocate_exact_size(egui::vec2(HANDLE_WIDTH, row_height), Sense::drag());
+                ui.painter().line_segment(
+                    [handle.center_top(), handle.center_bottom()],
+                    ui.visuals().widgets.noninteractive.bg_stroke,
+                );
+                if response.dragged() {
+                    self.widths[i] =
+                        (self.widths[i] + response.drag_delta().x).max(MIN_COLUMN_WIDTH);
+                }
+            }
+        });
+
+        if resort {
+            if let Some(mut rows) = self.rows.take() {
+                self.sort(&mut rows);
+                self.rows = Some(rows);
+            }
+        }
+
+        let rows = self.rows.take().unwrap_or_default();
+        let mut deleted = None;
+        ScrollArea::auto_sized().show_rows(ui, row_height, rows.len(), |ui, range| {
+            for row in &rows[range] {
+                ui.horizontal(|ui| {
+                    ui.set_min_height(row_height);
+                    ui.spacing_mut().item_spacing.x = 0.;
+                    for (i, column) in Column::ALL.iter().enumerate() {
+                        let (rect, _) = ui.allocate_exact_size(
+                            egui::vec2(self.widths[i] + HANDLE_WIDTH, row_height),
+                            Sense::hover(),
+                        );
+                        let rect =
+                            Rect::from_min_max(rect.min, rect.max - egui::vec2(HANDLE_WIDTH, 0.));
+                        if *column == Column::Percent {
+                            let mut bar = rect.shrink(2.);
+                            bar.max.x = bar.min.x + bar.width() * row.percent;
+                            ui.painter()
+                                .rect_filled(bar, 2., bar_style.color(row.modified));
+                        }
+                        ui.painter().sub_region(rect).text(
+                            rect.left_center(),
+                            Align2::LEFT_CENTER,
+                            self.cell_text(row, *column),
+                            TextStyle::Body,
+                            text_color,
+                        );
+                    }
+                    if can_delete(&row.path) && ui.button("Del").clicked() {
+                        deleted = Some(row.clone());
+                    }
+                });
+            }
+        });
+        self.rows = Some(rows);
+        if let Some(row) = deleted {
+            self.removals.delete(&row.path, row.is_dir, &del_sender);
+        }
+    }
+}
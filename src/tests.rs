@@ -23,3 +23,1564 @@ fn walk() {
 
     // use walkdir::WalkDir;
 }
+
+/// A storage that only lives in memory
+#[derive(Default)]
+struct MemoryStorage(std::collections::HashMap<String, String>);
+
+impl epi::Storage for MemoryStorage {
+    fn get_string(&self, key: &str) -> Option<String> {
+        self.0.get(key).cloned()
+    }
+
+    fn set_string(&mut self, key: &str, value: String) {
+        self.0.insert(key.to_string(), value);
+    }
+
+    fn flush(&mut self) {}
+}
+
+#[test]
+fn settings_roundtrip() {
+    let mut storage = MemoryStorage::default();
+    let mut app = MyApp {
+        scan_path: "/tmp/somewhere".into(),
+        max_files: 42,
+        allow_delete: true,
+        dirs_table_view: true,
+        max_timeline_files: 25,
+        max_archive_entries: 30,
+        max_compression_estimates: 15,
+        max_owner_files: 20,
+        max_dupe_groups: 35,
+        max_location_entries: 40,
+        filter_chain: vec![Filter::MinSize(7), Filter::MaxResults(3)],
+        ..Default::default()
+    };
+    epi::App::save(&mut app, &mut storage);
+
+    let restored: MyApp = epi::get_value(&storage, epi::APP_KEY).unwrap();
+    assert_eq!(restored.scan_path, "/tmp/somewhere");
+    assert_eq!(restored.max_files, 42);
+    assert!(restored.allow_delete);
+    assert!(restored.dirs_table_view && !restored.files_table_view);
+    assert_eq!(restored.max_timeline_files, 25);
+    assert_eq!(restored.max_archive_entries, 30);
+    assert_eq!(restored.max_compression_estimates, 15);
+    assert_eq!(restored.max_owner_files, 20);
+    assert_eq!(restored.max_dupe_groups, 35);
+    assert_eq!(restored.max_location_entries, 40);
+    assert_eq!(
+        restored.filter_chain,
+        vec![Filter::MinSize(7), Filter::MaxResults(3)]
+    );
+    // runtime state is not persisted
+    assert!(restored.ready);
+}
+
+#[test]
+fn recent_paths() {
+    let mut recent = vec![];
+    for i in 0..MAX_RECENT_PATHS + 3 {
+        remember_path(&mut recent, &format!("/data/{}", i));
+    }
+    assert_eq!(recent.len(), MAX_RECENT_PATHS);
+    assert_eq!(recent[0], format!("/data/{}", MAX_RECENT_PATHS + 2));
+
+    // Rescanning a known path moves it to the top instead of adding it again
+    remember_path(&mut recent, "/data/5");
+    assert_eq!(recent.len(), MAX_RECENT_PATHS);
+    assert_eq!(recent[0], "/data/5");
+    assert_eq!(recent.iter().filter(|p| *p == "/data/5").count(), 1);
+}
+
+#[test]
+fn scan_targets() {
+    use browser::{classify_path, path_completions, ScanTarget};
+
+    let root = std::env::temp_dir().join("birdseye_scan_targets");
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(root.join("projects")).unwrap();
+    std::fs::create_dir_all(root.join("photos")).unwrap();
+    std::fs::write(root.join("notes.txt"), "not an archive").unwrap();
+    std::fs::write(root.join("fake.zip"), b"PK\x03\x04rest").unwrap();
+
+    assert_eq!(classify_path(&root), ScanTarget::Directory);
+    assert_eq!(
+        classify_path(root.join("fake.zip")),
+        ScanTarget::Archive(archive::ArchiveFormat::Zip)
+    );
+    assert!(!classify_path(root.join("notes.txt")).is_valid());
+    assert!(!classify_path(root.join("typo")).is_valid());
+    assert!(!classify_path("").is_valid());
+
+    let prefix = root.join("p").to_string_lossy().to_string();
+    assert_eq!(
+        path_completions(&prefix),
+        vec![
+            root.join("photos").to_string_lossy().to_string(),
+            root.join("projects").to_string_lossy().to_string()
+        ]
+    );
+    let prefix = root.join("pr").to_string_lossy().to_string();
+    assert_eq!(path_completions(&prefix).len(), 1);
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn merge_roots() {
+    let base = std::env::temp_dir().join("birdseye_merge_roots");
+    let _ = std::fs::remove_dir_all(&base);
+    std::fs::create_dir_all(base.join("one/sub")).unwrap();
+    std::fs::create_dir_all(base.join("two")).unwrap();
+    std::fs::write(base.join("one/sub/a.txt"), vec![0u8; 100]).unwrap();
+    std::fs::write(base.join("one/b.log"), vec![0u8; 250]).unwrap();
+    std::fs::write(base.join("two/c.txt"), vec![0u8; 200]).unwrap();
+
+    let mut roots = vec![];
+    for name in &["one", "two"] {
+        let mut root = ScanRoot::new(&base.join(name).to_string_lossy());
+        root.info = diskspace_insight::scan(&root.path);
+        roots.push(root);
+    }
+    let merged = merge_infos(roots.iter().map(|r| &r.info));
+
+    assert_eq!(merged.combined_size, 550);
+    assert_eq!(merged.files.len(), 3);
+    assert_eq!(merged.files_by_size[0].size, 250);
+    assert_eq!(merged.types_by_size[0].ext, "txt");
+    assert_eq!(merged.types_by_size[0].size, 300);
+    assert!(merged.tree.contains_key(&base.join("one/sub")));
+    assert!(merged.tree.contains_key(&base.join("two")));
+
+    let sub = base.join("one/sub").to_string_lossy().to_string();
+    assert_eq!(overlapping_root(&roots, &sub), Some(roots[0].path.as_str()));
+    let parent = base.to_string_lossy().to_string();
+    assert!(overlapping_root(&roots, &parent).is_some());
+    let other = base.join("three").to_string_lossy().to_string();
+    assert_eq!(overlapping_root(&roots, &other), None);
+
+    // Removals are applied in place, without merging again
+    let mut merged = merged;
+    roots::remove(&mut merged, &base.join("one/sub"));
+    roots::remove(&mut merged, &base.join("one/b.log"));
+    assert_eq!(merged.combined_size, 200);
+    assert_eq!(merged.files_by_size.len(), 1);
+    assert_eq!(merged.types_by_size.len(), 1);
+    assert!(!merged.filetypes.contains_key("log"));
+    let one = &merged.tree[&base.join("one")];
+    assert_eq!(one.combined_size, 0);
+    assert!(one.files.is_empty() && one.directories.is_empty());
+    assert!(!merged
+        .dirs_by_size
+        .iter()
+        .any(|d| d.path == base.join("one/sub")));
+
+    let _ = std::fs::remove_dir_all(&base);
+}
+
+#[test]
+fn zoom_subtree() {
+    let base = std::env::temp_dir().join("birdseye_zoom_subtree");
+    let _ = std::fs::remove_dir_all(&base);
+    std::fs::create_dir_all(base.join("projects/foo/src")).unwrap();
+    std::fs::create_dir_all(base.join("music")).unwrap();
+    std::fs::write(base.join("projects/foo/src/main.rs"), vec![0u8; 10]).unwrap();
+    std::fs::write(base.join("projects/foo/data.bin"), vec![0u8; 40]).unwrap();
+    std::fs::write(base.join("music/song.ogg"), vec![0u8; 500]).unwrap();
+
+    let info = diskspace_insight::scan(&base);
+    let foo = base.join("projects/foo");
+    let sub = subtree_info(&info, &foo);
+
+    assert_eq!(sub.combined_size, 50);
+    assert_eq!(sub.files_by_size.len(), 2);
+    assert_eq!(sub.files_by_size[0].path, foo.join("data.bin"));
+    assert_eq!(sub.types_by_size.len(), 2);
+    assert!(sub.tree.contains_key(&foo.join("src")));
+    assert!(!sub.tree.contains_key(&base.join("music")));
+    assert!(sub.dirs_by_size.iter().all(|d| d.path.starts_with(&foo)));
+
+    let _ = std::fs::remove_dir_all(&base);
+}
+
+#[test]
+fn table_rows() {
+    let base = std::env::temp_dir().join("birdseye_table_rows");
+    let _ = std::fs::remove_dir_all(&base);
+    std::fs::create_dir_all(base.join("a/b")).unwrap();
+    std::fs::write(base.join("a/b/one.txt"), vec![0u8; 30]).unwrap();
+    std::fs::write(base.join("a/two.txt"), vec![0u8; 10]).unwrap();
+
+    let (info, extras) = scan::scan(&base, false, true, |_, _| {}, u128::MAX);
+    let dirs = table::dir_rows(&info, &extras.stats, &extras.owners);
+    let a = dirs.iter().find(|r| r.path == base.join("a")).unwrap();
+    assert_eq!(a.count, 3);
+    assert_eq!(a.size, 40);
+    let b = dirs.iter().find(|r| r.path == base.join("a/b")).unwrap();
+    assert_eq!(b.count, 1);
+    assert!((b.percent - 0.75).abs() < 0.001);
+
+    let files = table::file_rows(&info, &extras.owners);
+    let two = files.iter().find(|r| r.name == "two.txt").unwrap();
+    assert_eq!(two.ext, "txt");
+    assert!((two.percent - 0.25).abs() < 0.001);
+    // Owners come from the scan
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let uid = std::fs::metadata(base.join("a/two.txt")).unwrap().uid();
+        assert_eq!(two.owner, Some(uid));
+        assert_eq!(a.owner, Some(uid));
+    }
+
+    // Only what was removed is reported as deleted, directories arrive later
+    let mut removals = cleanup::Removals::default();
+    let (del_sender, del_receiver) = channel();
+    removals.delete(&base.join("missing.txt"), false, &del_sender);
+    assert!(del_receiver.try_recv().is_err());
+    assert_eq!(removals.errors.len(), 1);
+    removals.delete(&two.path, false, &del_sender);
+    assert_eq!(del_receiver.try_recv().unwrap(), base.join("a/two.txt"));
+    removals.delete(&a.path, true, &del_sender);
+    while removals.poll(&del_sender) {
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(del_receiver.try_recv().unwrap(), base.join("a"));
+    assert!(!base.join("a").exists());
+
+    let _ = std::fs::remove_dir_all(&base);
+}
+
+#[test]
+fn id_names() {
+    let passwd = "# comment\nroot:x:0:0:root:/root:/bin/bash\nalice:x:1000:1000::/home/alice:/bin/sh\nbroken";
+    let names = owners::parse_id_names(passwd);
+    assert_eq!(names.len(), 2);
+    assert_eq!(names[&0], "root");
+    assert_eq!(names[&1000], "alice");
+}
+
+#[test]
+fn item_counts() {
+    let base = std::env::temp_dir().join("birdseye_item_counts");
+    let _ = std::fs::remove_dir_all(&base);
+    std::fs::create_dir_all(base.join("cache/x/y")).unwrap();
+    std::fs::create_dir_all(base.join("big")).unwrap();
+    for i in 0..20 {
+        std::fs::write(base.join(format!("cache/x/{}.tmp", i)), b"t").unwrap();
+    }
+    std::fs::write(base.join("cache/x/y/z.tmp"), b"t").unwrap();
+    std::fs::write(base.join("big/blob.bin"), vec![0u8; 1000]).unwrap();
+
+    let (mut info, mut extras) = scan::scan(&base, false, true, |_, _| {}, u128::MAX);
+    let stats = &extras.stats;
+    let cache = &stats[&base.join("cache")];
+    assert_eq!(cache.file_count, 21);
+    assert_eq!(cache.dir_count, 2);
+    assert_eq!(stats[&base].items(), 21 + 1 + 4);
+
+    // Recomputing gives what the scan counted
+    let mut recomputed = HashMap::new();
+    update_stats(&info, &mut recomputed, &base);
+    assert_eq!(recomputed[&base].items(), stats[&base].items());
+    assert_eq!(recomputed[&base].newest, stats[&base].newest);
+
+    // Ranked by everything below, not only what a directory directly contains
+    let by_count = roots::dirs_by_count(&info, stats);
+    assert_eq!(
+        by_count[..3],
+        [base.clone(), base.join("cache"), base.join("cache/x")]
+    );
+    assert_eq!(info.dirs_by_size[0].path, base.join("big"));
+
+    // A removal updates the counts of the directories above it
+    let removed = base.join("cache/x/y");
+    roots::remove(&mut info, &removed);
+    extras.remove(&removed);
+    update_stats(&info, &mut extras.stats, &removed);
+    assert!(!extras.stats.contains_key(&removed));
+    assert_eq!(extras.stats[&base.join("cache")].items(), 20 + 1);
+    assert_eq!(extras.stats[&base].items(), 21 + 3);
+
+    #[cfg(unix)]
+    {
+        let usage = fs_usage(&base).unwrap();
+        assert!(usage.total_bytes > 0);
+        assert!(usage.used_bytes <= usage.total_bytes);
+        // Only a scan of the whole filesystem can tell what it missed
+        assert!(!usage.mount_point);
+        let mut root = ScanRoot::new(&base.to_string_lossy());
+        root.usage = Some(usage);
+        assert_eq!(root.unscanned_bytes(), None);
+        assert!(fs_usage("/").unwrap().mount_point);
+        root.usage = fs_usage("/");
+        assert!(root.unscanned_bytes().is_some());
+    }
+
+    let _ = std::fs::remove_dir_all(&base);
+}
+
+#[test]
+fn newest_modification() {
+    use std::time::{Duration, SystemTime};
+    let base = std::env::temp_dir().join("birdseye_newest_modification");
+    let _ = std::fs::remove_dir_all(&base);
+    std::fs::create_dir_all(base.join("a")).unwrap();
+    std::fs::create_dir_all(base.join("b")).unwrap();
+    let year = |y: u64| SystemTime::UNIX_EPOCH + Duration::from_secs((y - 1970) * 365 * 86400);
+    for (name, y) in &[
+        ("a/old.txt", 2000),
+        ("a/new.txt", 2020),
+        ("b/mid.txt", 2010),
+    ] {
+        let file = std::fs::File::create(base.join(name)).unwrap();
+        file.set_modified(year(*y)).unwrap();
+    }
+
+    let (mut info, mut extras) = scan::scan(&base, false, true, |_, _| {}, u128::MAX);
+    assert_eq!(extras.stats[&base.join("a")].newest, year(2020));
+    assert_eq!(extras.stats[&base].newest, year(2020));
+
+    // The newest file is gone, so the next newest takes its place
+    let removed = base.join("a/new.txt");
+    roots::remove(&mut info, &removed);
+    extras.remove(&removed);
+    update_stats(&info, &mut extras.stats, &removed);
+    assert_eq!(extras.stats[&base.join("a")].newest, year(2000));
+    assert_eq!(extras.stats[&base].newest, year(2010));
+
+    let _ = std::fs::remove_dir_all(&base);
+}
+
+#[test]
+fn age_colors() {
+    use std::time::{Duration, SystemTime};
+    let now = SystemTime::now();
+    let year = Duration::from_secs(365 * 86400);
+
+    assert!(age::staleness(now) < 0.01);
+    assert!(age::staleness(now - year) < age::staleness(now - year * 3));
+    assert_eq!(age::staleness(now - year * 20), 1.);
+    assert_eq!(age_color(now - year * 20), age_color(now - year * 10));
+    assert_ne!(age_color(now), age_color(now - year));
+
+    let style = BarStyle {
+        accent: Color32::RED,
+        by_age: false,
+    };
+    assert_eq!(style.color(now - year), Color32::RED);
+}
+
+#[test]
+fn timeline_buckets() {
+    use std::time::{Duration, SystemTime};
+    assert_eq!(timeline::civil_from_days(0), (1970, 1, 1));
+    assert_eq!(timeline::civil_from_days(-1), (1969, 12, 31));
+    assert_eq!(timeline::civil_from_days(19_416), (2023, 2, 28));
+    assert_eq!(timeline::civil_from_days(19_782), (2024, 2, 29));
+
+    let at = |days: u64| SystemTime::UNIX_EPOCH + Duration::from_secs(days * 86400 + 3600);
+    let file = |name: &str, size: u64, days: u64| File {
+        size,
+        ext: None,
+        path: PathBuf::from(name),
+        modified: at(days),
+        hash: 0,
+    };
+    let mut info = DirInfo::new();
+    info.files = vec![
+        file("jan_small", 10, 19_360),
+        file("jan_big", 30, 19_370),
+        file("mar", 5, 19_420),
+        file("old", 1, 0),
+    ];
+
+    let months = bucket_files(&info, Granularity::Month);
+    let labels: Vec<String> = months.keys().map(|b| b.label()).collect();
+    assert_eq!(labels, vec!["1970-01", "2023-01", "2023-03"]);
+    let jan = &months[&Bucket {
+        year: 2023,
+        month: 1,
+    }];
+    assert_eq!((jan.bytes, jan.count), (40, 2));
+    assert_eq!(info.files[jan.files[0]].path, PathBuf::from("jan_big"));
+    assert_eq!(jan.newest, at(19_370));
+
+    let years = bucket_files(&info, Granularity::Year);
+    assert_eq!(years.len(), 2);
+    assert_eq!(
+        years[&Bucket {
+            year: 2023,
+            month: 0
+        }]
+            .count,
+        3
+    );
+}
+
+#[test]
+fn duplicates() {
+    let base = std::env::temp_dir().join("birdseye_duplicates");
+    let _ = std::fs::remove_dir_all(&base);
+    std::fs::create_dir_all(base.join("copies")).unwrap();
+    let big: Vec<u8> = (0..20_000u32).map(|i| (i % 251) as u8).collect();
+    let mut big_changed = big.clone();
+    big_changed[10_000] ^= 1;
+    std::fs::write(base.join("a.bin"), &big).unwrap();
+    std::fs::write(base.join("copies/a.bin"), &big).unwrap();
+    std::fs::write(base.join("copies/a2.bin"), &big).unwrap();
+    // Same size, same start and end, different middle
+    std::fs::write(base.join("almost.bin"), &big_changed).unwrap();
+    std::fs::write(base.join("small1.txt"), b"hello").unwrap();
+    std::fs::write(base.join("small2.txt"), b"hello").unwrap();
+    std::fs::write(base.join("other.txt"), b"world").unwrap();
+    std::fs::write(base.join("empty1"), b"").unwrap();
+    std::fs::write(base.join("empty2"), b"").unwrap();
+
+    let info = diskspace_insight::scan(&base);
+    let files = info
+        .files
+        .iter()
+        .map(|f| (f.path.clone(), f.size))
+        .collect();
+    let mut groups = dupes::find_duplicates(files, &|_| ());
+    assert_eq!(groups.len(), 2);
+    assert_eq!(groups[0].paths.len(), 3);
+    assert_eq!(groups[0].reclaimable(), 40_000);
+    assert_eq!(
+        groups[1].paths,
+        vec![base.join("small1.txt"), base.join("small2.txt")]
+    );
+
+    // Copies are reported one by one
+    let (sender, receiver) = channel();
+    let report = |msg| {
+        let _ = sender.send(msg);
+    };
+    dupes::resolve(&groups[0], DupeAction::HardLink, &report);
+    let linked: Vec<ResolveMessage> = receiver
+        .try_iter()
+        .filter(|m| !matches!(m, ResolveMessage::Progress { .. }))
+        .collect();
+    assert_eq!(linked.len(), 2);
+    for msg in linked {
+        match msg {
+            ResolveMessage::Linked(path) => forget_copy(&mut groups, &path),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+    // Without copies left, the group is gone
+    assert_eq!(groups.len(), 1);
+    assert_eq!(std::fs::read(base.join("copies/a2.bin")).unwrap(), big);
+
+    // Hard links are not reported again
+    let files = info
+        .files
+        .iter()
+        .map(|f| (f.path.clone(), f.size))
+        .collect();
+    #[cfg(unix)]
+    assert_eq!(dupes::find_duplicates(files, &|_| ()).len(), 1);
+
+    // A copy that can't be removed stays in the group, with the reason
+    groups[0].paths.push(base.join("missing.txt"));
+    spawn_resolve(groups[0].clone(), DupeAction::Delete, sender.clone());
+    let mut failed = vec![];
+    for msg in receiver.iter() {
+        match msg {
+            ResolveMessage::Removed(path) => forget_copy(&mut groups, &path),
+            ResolveMessage::Failed(path, _) => failed.push(path),
+            ResolveMessage::Done => break,
+            _ => (),
+        }
+    }
+    assert!(!base.join("small2.txt").exists());
+    assert_eq!(failed, vec![base.join("missing.txt")]);
+    assert_eq!(
+        groups[0].paths,
+        vec![base.join("small1.txt"), base.join("missing.txt")]
+    );
+
+    assert_eq!(
+        trash::encode_path(Path::new("/tmp/a b%.txt")),
+        "/tmp/a%20b%25.txt"
+    );
+
+    let _ = std::fs::remove_dir_all(&base);
+}
+
+#[test]
+fn duplicate_directories() {
+    let base = std::env::temp_dir().join("birdseye_duplicate_directories");
+    let _ = std::fs::remove_dir_all(&base);
+    for tree in &["project", "project (copy)", "other"] {
+        std::fs::create_dir_all(base.join(tree).join("src")).unwrap();
+        std::fs::write(base.join(tree).join("src/main.rs"), b"fn main() {}").unwrap();
+        std::fs::write(base.join(tree).join("Cargo.toml"), vec![b'x'; 100]).unwrap();
+    }
+    // Same names and sizes, different content
+    std::fs::write(base.join("other/Cargo.toml"), vec![b'y'; 100]).unwrap();
+    std::fs::create_dir_all(base.join("empty1")).unwrap();
+    std::fs::create_dir_all(base.join("empty2")).unwrap();
+
+    let info = diskspace_insight::scan(&base);
+    let groups = duplicate_dirs(&info, &HashSet::new(), &HashMap::new());
+    assert_eq!(groups.len(), 2);
+    assert_eq!(
+        groups[0].paths,
+        vec![base.join("project"), base.join("project (copy)")]
+    );
+    assert_eq!(groups[0].size, 112);
+    // Listed because of other/src, the copies inside the duplicates alone would be left out
+    assert_eq!(groups[1].paths.len(), 3);
+
+    let _ = std::fs::remove_dir_all(&base);
+}
+
+#[test]
+fn junk_dirs() {
+    let base = std::env::temp_dir().join("birdseye_junk_dirs");
+    let _ = std::fs::remove_dir_all(&base);
+    std::fs::create_dir_all(base.join("rust/target/debug")).unwrap();
+    std::fs::write(base.join("rust/Cargo.toml"), b"[package]").unwrap();
+    std::fs::write(base.join("rust/target/debug/app"), vec![0u8; 500]).unwrap();
+    // No Cargo.toml, so this is somebody's data
+    std::fs::create_dir_all(base.join("photos/target")).unwrap();
+    std::fs::write(base.join("photos/target/img.jpg"), vec![0u8; 900]).unwrap();
+    std::fs::create_dir_all(base.join("web/node_modules/a/node_modules/b")).unwrap();
+    std::fs::write(base.join("web/package.json"), b"{}").unwrap();
+    std::fs::write(base.join("web/node_modules/a/node_modules/b/i.js"), b"x").unwrap();
+    std::fs::create_dir_all(base.join("py/__pycache__")).unwrap();
+    std::fs::write(base.join("py/__pycache__/m.pyc"), vec![0u8; 10]).unwrap();
+
+    let info = diskspace_insight::scan(&base);
+    let junk = find_junk(&info, &HashSet::new(), &HashMap::new());
+    let paths: Vec<&PathBuf> = junk.iter().map(|j| &j.path).collect();
+    assert_eq!(
+        paths,
+        vec![
+            &base.join("rust/target"),
+            &base.join("py/__pycache__"),
+            &base.join("web/node_modules")
+        ]
+    );
+    let totals = totals_by_kind(&junk);
+    assert_eq!(totals[0], (1, 500));
+
+    // Removal happens in the background, failures are reported per directory
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let paths = vec![base.join("rust/target"), base.join("missing")];
+    spawn_cleanup(Removal::Delete, paths, sender);
+    let (mut removed, mut failed) = (vec![], vec![]);
+    loop {
+        match receiver.recv().unwrap() {
+            CleanupMessage::Progress { total, .. } => assert_eq!(total, 2),
+            CleanupMessage::Removed(path) => removed.push(path),
+            CleanupMessage::Failed(path, _) => failed.push(path),
+            CleanupMessage::Done => break,
+        }
+    }
+    assert_eq!(removed, vec![base.join("rust/target")]);
+    assert_eq!(failed, vec![base.join("missing")]);
+    assert!(!base.join("rust/target").exists());
+
+    let _ = std::fs::remove_dir_all(&base);
+}
+
+#[test]
+fn location_sizes() {
+    let base = std::env::temp_dir().join("birdseye_location_sizes");
+    let _ = std::fs::remove_dir_all(&base);
+    std::fs::create_dir_all(base.join("app1/sub")).unwrap();
+    std::fs::create_dir_all(base.join("app2")).unwrap();
+    std::fs::write(base.join("app1/sub/a"), vec![0u8; 300]).unwrap();
+    std::fs::write(base.join("app2/b"), vec![0u8; 100]).unwrap();
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(base.join("app1"), base.join("app2/link")).unwrap();
+
+    assert_eq!(locations::disk_usage(&base.join("app1")), 300);
+    let location = Location {
+        name: "test",
+        path: base.clone(),
+        description: "",
+        breakdown: true,
+    };
+    let measured = locations::measure(&location);
+    assert_eq!(measured.entries[0], (base.join("app1"), 300));
+    // The symlink counts as itself, not as the directory it points to
+    assert!(measured.size < 600);
+
+    assert!(known_locations().iter().any(|l| l.name == "Trash"));
+    let _ = std::fs::remove_dir_all(&base);
+}
+
+#[test]
+fn trash_items() {
+    let (path, date) = trash::parse_trashinfo(
+        "[Trash Info]\nPath=/home/user/My%20Files/r%C3%A9sum%C3%A9.pdf\nDeletionDate=2021-04-01T10:20:30\n",
+    );
+    assert_eq!(path.unwrap(), "/home/user/My Files/résumé.pdf");
+    assert_eq!(date.unwrap(), "2021-04-01T10:20:30");
+    assert_eq!(trash::decode_path("100%"), "100%");
+
+    // A trash at the top of another mount, with paths relative to the mount
+    let base = std::env::temp_dir().join("birdseye_trash_items");
+    let _ = std::fs::remove_dir_all(&base);
+    let trash_dir = base.join(".Trash-1000");
+    std::fs::create_dir_all(trash_dir.join("files/photos")).unwrap();
+    std::fs::create_dir_all(trash_dir.join("info")).unwrap();
+    std::fs::write(trash_dir.join("files/photos/a.jpg"), vec![0u8; 200]).unwrap();
+    std::fs::write(
+        trash_dir.join("info/photos.trashinfo"),
+        "[Trash Info]\nPath=data/photos\nDeletionDate=2021-04-01T10:20:30\n",
+    )
+    .unwrap();
+    std::fs::write(trash_dir.join("files/note.txt"), b"note").unwrap();
+
+    let items = trash::list_trash(&trash_dir);
+    assert_eq!(items.len(), 2);
+    assert_eq!(items[0].size, 200);
+    assert_eq!(items[0].original, Some(base.join("data/photos")));
+    assert_eq!(items[1].original, None);
+
+    // Restoring and purging happen in the background, item by item
+    use trash::{TrashAction, TrashMessage};
+    let handled = |action, items: Vec<trash::TrashItem>| {
+        let (sender, receiver) = channel();
+        trash::spawn_action(action, items, sender);
+        receiver
+            .iter()
+            .take_while(|m| *m != TrashMessage::Done)
+            .filter(|m| !matches!(m, TrashMessage::Progress { .. }))
+            .collect::<Vec<_>>()
+    };
+    let restored = handled(TrashAction::Restore, items.clone());
+    assert_eq!(restored.len(), 2);
+    assert_eq!(
+        restored[0],
+        TrashMessage::Handled {
+            path: items[0].path.clone(),
+            restored: Some(base.join("data/photos"))
+        }
+    );
+    assert!(matches!(&restored[1], TrashMessage::Failed(p, _) if *p == items[1].path));
+    assert!(base.join("data/photos/a.jpg").exists());
+    assert!(!trash_dir.join("info/photos.trashinfo").exists());
+
+    let purged = handled(TrashAction::Purge, vec![items[1].clone()]);
+    assert_eq!(
+        purged,
+        vec![TrashMessage::Handled {
+            path: items[1].path.clone(),
+            restored: None
+        }]
+    );
+    assert!(trash::list_trash(&trash_dir).is_empty());
+
+    let _ = std::fs::remove_dir_all(&base);
+}
+
+/// A ustar header block, without a valid checksum
+fn tar_header(name: &str, size: usize, typeflag: u8) -> Vec<u8> {
+    let mut header = vec![0u8; 512];
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    let size = format!("{:011o}", size);
+    header[124..135].copy_from_slice(size.as_bytes());
+    header[136..147].copy_from_slice(b"13000000000");
+    header[156] = typeflag;
+    header[257..263].copy_from_slice(b"ustar\0");
+    header
+}
+
+#[test]
+fn archives() {
+    use archive::{detect, scan_archive, ArchiveFormat};
+    use std::io::Write;
+
+    let base = std::env::temp_dir().join("birdseye_archives");
+    let _ = std::fs::remove_dir_all(&base);
+    std::fs::create_dir_all(&base).unwrap();
+
+    let zip_path = base.join("backup.zip");
+    let mut zip = zip::ZipWriter::new(std::fs::File::create(&zip_path).unwrap());
+    let options = zip::write::FileOptions::default();
+    zip.add_directory("docs/", options).unwrap();
+    zip.start_file("docs/notes.txt", options).unwrap();
+    zip.write_all(&[b'a'; 5000]).unwrap();
+    zip.start_file("../escape.bin", options).unwrap();
+    zip.write_all(&[1, 2, 3]).unwrap();
+    zip.finish().unwrap();
+
+    assert_eq!(detect(&zip_path), Some(ArchiveFormat::Zip));
+    let (info, details) = scan_archive(&zip_path).unwrap();
+    assert_eq!(info.combined_size, 5003);
+    assert_eq!(info.tree[&zip_path].combined_size, 5003);
+    assert_eq!(info.tree[&zip_path.join("docs")].size, 5000);
+    // Entries can't point outside of the archive
+    assert!(info
+        .files
+        .iter()
+        .any(|f| f.path == zip_path.join("escape.bin")));
+    assert!(details.compressed[&zip_path.join("docs/notes.txt")] < 5000);
+
+    let mut tar = vec![];
+    tar.extend(tar_header("src/", 0, b'5'));
+    tar.extend(tar_header("src/main.rs", 600, b'0'));
+    tar.extend(vec![b'x'; 600]);
+    tar.extend(vec![0u8; 424]);
+    let long_name = format!("{}/file.txt", "d".repeat(120));
+    tar.extend(tar_header("././@LongLink", long_name.len(), b'L'));
+    tar.extend(long_name.as_bytes());
+    tar.extend(vec![0u8; 512 - long_name.len()]);
+    tar.extend(tar_header("truncated", 10, b'0'));
+    tar.extend(vec![b'y'; 10]);
+    tar.extend(vec![0u8; 502]);
+    tar.extend(vec![0u8; 1024]);
+
+    let tar_path = base.join("src.tar");
+    std::fs::write(&tar_path, &tar).unwrap();
+    assert_eq!(detect(&tar_path), Some(ArchiveFormat::Tar));
+    let zst_path = base.join("src.tar.zst");
+    std::fs::write(&zst_path, zstd::encode_all(&tar[..], 3).unwrap()).unwrap();
+    assert_eq!(detect(&zst_path), Some(ArchiveFormat::TarZst));
+
+    let (info, details) = scan_archive(&zst_path).unwrap();
+    assert_eq!(info.files.len(), 2);
+    assert_eq!(info.combined_size, 610);
+    let long = info.tree[&zst_path.join("d".repeat(120))].files[0].clone();
+    assert_eq!(long.size, 10);
+    assert_eq!(
+        long.modified,
+        std::time::UNIX_EPOCH + std::time::Duration::from_secs(0o13000000000)
+    );
+    assert!(details.archive_size < 610);
+
+    // A long name that claims to be a gigabyte is refused instead of read into memory
+    let mut huge = tar_header("././@LongLink", 1 << 30, b'L');
+    huge.extend(vec![b'd'; 512]);
+    let huge_path = base.join("huge.tar");
+    std::fs::write(&huge_path, &huge).unwrap();
+    assert!(scan_archive(&huge_path).is_err());
+
+    std::fs::write(base.join("plain.gz"), b"\x1f\x8bnot a tarball").unwrap();
+    assert_eq!(detect(&base.join("plain.gz")), None);
+    std::fs::write(
+        base.join("a.7z"),
+        [0x37, 0x7a, 0xbc, 0xaf, 0x27, 0x1c, 0, 4],
+    )
+    .unwrap();
+    assert!(!browser::classify_path(base.join("a.7z")).is_valid());
+
+    let _ = std::fs::remove_dir_all(&base);
+}
+
+#[test]
+fn nested_archives() {
+    use archive::{descend_archives, in_nested_archive};
+    use std::io::Write;
+
+    let base = std::env::temp_dir().join("birdseye_nested_archives");
+    let _ = std::fs::remove_dir_all(&base);
+    std::fs::create_dir_all(base.join("backups")).unwrap();
+    std::fs::write(base.join("backups/notes.txt"), vec![b'n'; 100]).unwrap();
+    let zip_path = base.join("backups/db.jar");
+    let mut zip = zip::ZipWriter::new(std::fs::File::create(&zip_path).unwrap());
+    let options = zip::write::FileOptions::default();
+    zip.start_file("dump/db.sql", options).unwrap();
+    zip.write_all(&[b'x'; 9000]).unwrap();
+    zip.start_file("node_modules/x.js", options).unwrap();
+    zip.write_all(b"x").unwrap();
+    zip.finish().unwrap();
+    std::fs::write(base.join("package.json"), b"{}").unwrap();
+
+    let mut info = diskspace_insight::scan(&base);
+    let files_before = info.files.len();
+    let size_before = info.combined_size;
+    descend_archives(&mut info);
+
+    // The archive counts once, with its file size
+    assert_eq!(info.files.len(), files_before);
+    assert_eq!(info.combined_size, size_before);
+    assert_eq!(info.tree[&zip_path.join("dump")].combined_size, 9000);
+
+    let archives = archive_roots(&info);
+    assert_eq!(archives.iter().collect::<Vec<_>>(), vec![&zip_path]);
+    assert!(in_archive(&zip_path.join("dump/db.sql"), &archives));
+    assert!(!in_archive(&zip_path, &archives));
+    assert!(in_nested_archive(&info, &zip_path.join("dump"), &archives));
+
+    let mut stats = HashMap::new();
+    update_stats(&info, &mut stats, &base);
+    assert_eq!(stats[&zip_path].file_count, 2);
+    // The archive is one file of backups, its contents are not counted again
+    assert_eq!(stats[&base.join("backups")].items(), 2);
+
+    assert!(find_junk(&info, &archives, &HashMap::new()).is_empty());
+    let _ = std::fs::remove_dir_all(&base);
+}
+
+#[test]
+fn compression_estimate() {
+    use compress::{build_report, sample_ratio};
+
+    let base = std::env::temp_dir().join("birdseye_compression_estimate");
+    let _ = std::fs::remove_dir_all(&base);
+    std::fs::create_dir_all(base.join("logs")).unwrap();
+    std::fs::create_dir_all(base.join("media")).unwrap();
+    let log: Vec<u8> = b"2021-04-01 INFO request handled\n".repeat(2000);
+    std::fs::write(base.join("logs/app.log"), &log).unwrap();
+    // Pseudo random bytes don't compress
+    let mut x: u32 = 1;
+    let noise: Vec<u8> = (0..64_000)
+        .map(|_| {
+            x ^= x << 13;
+            x ^= x >> 17;
+            x ^= x << 5;
+            x as u8
+        })
+        .collect();
+    std::fs::write(base.join("media/clip.mp4"), &noise).unwrap();
+
+    let log_ratio = sample_ratio(&[base.join("logs/app.log")]).unwrap();
+    assert!(log_ratio < 0.05);
+    assert!(sample_ratio(&[base.join("media/clip.mp4")]).unwrap() > 0.99);
+    assert_eq!(sample_ratio(&[base.join("missing")]), None);
+
+    let mut ratios = HashMap::new();
+    ratios.insert("log".to_string(), log_ratio);
+    let files = vec![
+        (base.join("logs/app.log"), 1000, Some("log".to_string())),
+        (base.join("logs/old/old.log"), 3000, Some("log".to_string())),
+        (base.join("media/clip.mp4"), 5000, Some("mp4".to_string())),
+    ];
+    let tree: HashSet<PathBuf> = vec![
+        base.clone(),
+        base.join("logs"),
+        base.join("logs/old"),
+        base.join("media"),
+    ]
+    .into_iter()
+    .collect();
+    let report = build_report(&files, &tree, &ratios);
+    assert_eq!(report.types.len(), 1);
+    assert_eq!(report.types[0].size, 4000);
+    // Directories add up everything below them, but nothing above the tree
+    let dir = |path: PathBuf| {
+        report
+            .dirs
+            .iter()
+            .find(|e| e.name == path.to_string_lossy())
+            .unwrap()
+    };
+    assert_eq!(report.dirs.len(), 3);
+    assert_eq!(dir(base.join("logs")).size, 4000);
+    assert!(dir(base.join("logs")).compressed < 200);
+    assert_eq!(dir(base.join("logs/old")).size, 3000);
+    assert_eq!(dir(base.clone()).size, 9000);
+    assert_eq!(compress::dir_label(dir(base.join("logs"))), "logs/");
+
+    let _ = std::fs::remove_dir_all(&base);
+}
+
+#[test]
+fn packing() {
+    use pack::{default_destination, pack, spawn_pack, PackMessage};
+
+    let base = std::env::temp_dir().join("birdseye_packing");
+    let _ = std::fs::remove_dir_all(&base);
+    let source = base.join("project");
+    let long_name = "n".repeat(120);
+    std::fs::create_dir_all(source.join("src/empty")).unwrap();
+    std::fs::write(source.join("README"), "hello").unwrap();
+    std::fs::write(source.join("src").join(&long_name), vec![7u8; 3000]).unwrap();
+    #[cfg(unix)]
+    let owner = {
+        use std::os::unix::fs::MetadataExt;
+        // Running as root, the file gets an owner that can't be mistaken for a default 0
+        let _ = std::os::unix::fs::chown(source.join("README"), Some(1234), Some(5678));
+        let meta = std::fs::metadata(source.join("README")).unwrap();
+        (meta.uid(), meta.gid())
+    };
+
+    let destination = default_destination(&source);
+    assert_eq!(destination, base.join("project.tar.zst"));
+    let (size, _) = pack(&source, &destination, &|_, _| {}).unwrap();
+    #[cfg(unix)]
+    {
+        let tar = zstd::decode_all(std::fs::File::open(&destination).unwrap()).unwrap();
+        let header = tar
+            .chunks(512)
+            .find(|b| b.starts_with(b"project/README\0"))
+            .unwrap();
+        let number = |field: &[u8]| u32::from_str_radix(std::str::from_utf8(field).unwrap(), 8);
+        assert_eq!(number(&header[108..115]), Ok(owner.0));
+        assert_eq!(number(&header[116..123]), Ok(owner.1));
+    }
+    assert_eq!(
+        archive::detect(&destination),
+        Some(archive::ArchiveFormat::TarZst)
+    );
+    let (contents, archive_info) = archive::scan_archive(&destination).unwrap();
+    assert_eq!(archive_info.archive_size, size);
+    assert_eq!(contents.combined_size, 3005);
+    assert!(contents
+        .tree
+        .contains_key(&destination.join("project/src/empty")));
+    assert!(contents
+        .files
+        .iter()
+        .any(|f| f.path == destination.join("project/src").join(&long_name)));
+
+    // An existing archive is never overwritten, and packing into the source is refused
+    assert!(pack(&source, &destination, &|_, _| {}).is_err());
+    assert!(pack(&source, &source.join("self.tar.zst"), &|_, _| {}).is_err());
+    assert!(!source.join("self.tar.zst").exists());
+
+    let spawn = |destination: &PathBuf| {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        spawn_pack(source.clone(), destination.clone(), true, sender);
+        loop {
+            match receiver.recv().unwrap() {
+                PackMessage::Progress { done, total } => assert!(done <= total),
+                PackMessage::Done(result) => break result.unwrap(),
+            }
+        }
+    };
+
+    // A fifo can't go into the archive, so the original is kept as it is
+    #[cfg(unix)]
+    {
+        let fifo = source.join("pipe");
+        Command::new("mkfifo").arg(&fifo).output().unwrap();
+        let packed = spawn(&base.join("with_fifo.tar.zst"));
+        assert!(!packed.removed);
+        assert!(packed.kept.is_some());
+        assert!(packed.partly_removed.is_empty());
+        assert!(source.join("README").exists());
+        std::fs::remove_file(&fifo).unwrap();
+    }
+
+    // Names that aren't UTF-8 would be mangled, so such trees are not packed at all
+    #[cfg(unix)]
+    {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+        let odd = OsStr::from_bytes(b"caf\xe9");
+        let unpacked = base.join("odd.tar.zst");
+        std::fs::write(source.join(odd), b"x").unwrap();
+        let error = pack(&source, &unpacked, &|_, _| {}).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert!(!unpacked.exists());
+        std::fs::remove_file(source.join(odd)).unwrap();
+
+        std::os::unix::fs::symlink(odd, source.join("link")).unwrap();
+        let error = pack(&source, &unpacked, &|_, _| {}).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert!(!unpacked.exists());
+        std::fs::remove_file(source.join("link")).unwrap();
+    }
+
+    let second = base.join("second.tar.zst");
+    let packed = spawn(&second);
+    assert!(packed.removed);
+    assert!(packed.kept.is_none());
+    assert!(!source.exists());
+    assert_eq!(
+        packed.archive_size,
+        std::fs::metadata(&second).unwrap().len()
+    );
+
+    // The new archive shows up in a tree that contains its directory
+    let mut info = DirInfo::new();
+    info.tree.insert(
+        base.clone(),
+        Directory {
+            path: base.clone(),
+            ..Default::default()
+        },
+    );
+    roots::insert_file(
+        &mut info,
+        File {
+            size: packed.archive_size,
+            ext: Some("zst".to_string()),
+            path: second.clone(),
+            modified: SystemTime::now(),
+            hash: 0,
+        },
+    );
+    assert_eq!(info.tree[&base].files.len(), 1);
+    assert_eq!(info.tree[&base].combined_size, packed.archive_size);
+    assert_eq!(info.combined_size, packed.archive_size);
+
+    let _ = std::fs::remove_dir_all(&base);
+}
+
+#[test]
+fn moving() {
+    use relocate::{copy_tree, move_into};
+
+    let base = std::env::temp_dir().join("birdseye_moving");
+    let _ = std::fs::remove_dir_all(&base);
+    let source = base.join("photos");
+    std::fs::create_dir_all(source.join("2020")).unwrap();
+    std::fs::create_dir_all(base.join("disk")).unwrap();
+    std::fs::write(source.join("2020/a.jpg"), vec![1u8; 2000]).unwrap();
+    std::fs::write(source.join("b.jpg"), vec![2u8; 500]).unwrap();
+
+    // The copy that moves across filesystems use
+    let copy = base.join("copy");
+    let mut done = 0;
+    copy_tree(&source, &copy, &mut done, &|_| {}).unwrap();
+    assert_eq!(done, 2500);
+    assert_eq!(
+        std::fs::read(copy.join("2020/a.jpg")).unwrap(),
+        vec![1u8; 2000]
+    );
+    assert!(copy_tree(&source, &copy, &mut done, &|_| {}).is_err());
+
+    // Nothing is overwritten, and nothing moves into itself
+    assert!(move_into(&source, &base, &|_, _| {}).is_err());
+    assert!(move_into(&source, &source.join("2020"), &|_, _| {}).is_err());
+    let moved = move_into(&source, &base.join("disk"), &|_, _| {}).unwrap();
+    assert_eq!(moved, base.join("disk/photos"));
+    assert!(!source.exists());
+    assert!(moved.join("2020/a.jpg").is_file());
+
+    // The known part of the tree moves along
+    let dir = |path: PathBuf, size, files: Vec<File>, directories: Vec<PathBuf>| Directory {
+        path,
+        size,
+        combined_size: size,
+        files,
+        directories,
+        parent: None,
+    };
+    let file = |path: PathBuf, size| File {
+        size,
+        ext: Some("jpg".to_string()),
+        path,
+        modified: SystemTime::UNIX_EPOCH,
+        hash: 0,
+    };
+    let a = file(source.join("2020/a.jpg"), 2000);
+    let b = file(source.join("b.jpg"), 500);
+    let dirs = vec![
+        dir(
+            source.clone(),
+            500,
+            vec![b.clone()],
+            vec![source.join("2020")],
+        ),
+        dir(source.join("2020"), 2000, vec![a.clone()], vec![]),
+    ];
+    let mut info = DirInfo::new();
+    info.tree
+        .insert(base.join("disk"), dir(base.join("disk"), 0, vec![], vec![]));
+    roots::graft(&mut info, &source, &moved, &dirs, &[a, b]);
+    assert_eq!(info.combined_size, 2500);
+    assert_eq!(info.tree[&base.join("disk")].combined_size, 2500);
+    assert_eq!(
+        info.tree[&base.join("disk")].directories,
+        vec![moved.clone()]
+    );
+    assert_eq!(info.tree[&moved].directories, vec![moved.join("2020")]);
+    assert_eq!(
+        info.tree[&moved.join("2020")].files[0].path,
+        moved.join("2020/a.jpg")
+    );
+    assert!(info.files.iter().all(|f| f.path.starts_with(&moved)));
+
+    // Outside of the tree nothing happens
+    let mut other = DirInfo::new();
+    roots::graft(&mut other, &source, &moved, &dirs, &[]);
+    assert!(other.tree.is_empty());
+
+    let _ = std::fs::remove_dir_all(&base);
+}
+
+#[cfg(unix)]
+#[test]
+fn relocating() {
+    use relocate::relocate_and_link;
+    use std::os::unix::fs::PermissionsExt;
+
+    let base = std::env::temp_dir().join("birdseye_relocating");
+    let _ = std::fs::remove_dir_all(&base);
+    let source = base.join("Steam");
+    std::fs::create_dir_all(source.join("games")).unwrap();
+    std::fs::create_dir_all(base.join("big")).unwrap();
+    std::fs::write(source.join("games/data.pak"), vec![3u8; 4000]).unwrap();
+
+    let (copy, warning) = relocate_and_link(&source, &base.join("big"), &|_, _| {}).unwrap();
+    assert_eq!(copy, base.join("big/Steam"));
+    assert_eq!(warning, None);
+    assert!(std::fs::symlink_metadata(&source)
+        .unwrap()
+        .file_type()
+        .is_symlink());
+    assert_eq!(std::fs::read_link(&source).unwrap(), copy);
+    assert_eq!(
+        std::fs::read(source.join("games/data.pak")).unwrap().len(),
+        4000
+    );
+    assert!(!base.join("Steam.birdseye-old").exists());
+
+    // A failure leaves everything as it was: here the copy can't be created
+    let other = base.join("other");
+    std::fs::create_dir_all(other.join("inner")).unwrap();
+    std::fs::create_dir_all(base.join("taken/other")).unwrap();
+    assert!(relocate_and_link(&other, &base.join("taken"), &|_, _| {}).is_err());
+    assert!(other.join("inner").is_dir());
+    // A backup left over from before is never overwritten
+    std::fs::create_dir_all(base.join("other.birdseye-old")).unwrap();
+    assert!(relocate_and_link(&other, &base.join("big"), &|_, _| {}).is_err());
+    assert!(!base.join("big/other").exists());
+    assert!(other.join("inner").is_dir());
+
+    // Here the copy succeeds, but the original can't be put aside in its read-only parent.
+    // The copy is removed again. Root can write anyway, so this needs a regular user.
+    let locked = base.join("locked");
+    std::fs::create_dir_all(locked.join("cache/inner")).unwrap();
+    std::fs::write(locked.join("cache/inner/blob"), vec![5u8; 300]).unwrap();
+    std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o555)).unwrap();
+    if unsafe { libc::geteuid() } != 0 {
+        assert!(relocate_and_link(&locked.join("cache"), &base.join("big"), &|_, _| {}).is_err());
+        assert!(!base.join("big/cache").exists());
+        assert!(locked.join("cache/inner/blob").is_file());
+        assert!(!locked.join("cache.birdseye-old").exists());
+    }
+    std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o755)).unwrap();
+    // Files and symlinks can't be relocated
+    assert!(relocate_and_link(&source, &base.join("taken"), &|_, _| {}).is_err());
+
+    let _ = std::fs::remove_dir_all(&base);
+}
+
+#[cfg(unix)]
+#[test]
+fn symlinks() {
+    use std::os::unix::fs::symlink;
+
+    let base = std::env::temp_dir().join("birdseye_symlinks");
+    let _ = std::fs::remove_dir_all(&base);
+    std::fs::create_dir_all(base.join("data/deep")).unwrap();
+    std::fs::write(base.join("data/deep/big.bin"), vec![0u8; 5000]).unwrap();
+    std::fs::create_dir_all(base.join("data/__pycache__")).unwrap();
+    std::fs::write(base.join("data/__pycache__/x.pyc"), b"x").unwrap();
+    symlink(base.join("data/deep/big.bin"), base.join("file_link")).unwrap();
+    symlink(base.join("data"), base.join("dir_link")).unwrap();
+    symlink("missing", base.join("broken")).unwrap();
+    // Following this would go around in circles
+    symlink(&base, base.join("data/loop")).unwrap();
+
+    // By default links are listed with their own small size, but not followed
+    let (info, extras) = scan::scan(&base, false, true, |_, _| {}, u128::MAX);
+    assert_eq!(extras.symlinks.len(), 4);
+    assert!(extras.symlinks[&base.join("broken")].broken);
+    assert_eq!(
+        extras.symlinks[&base.join("broken")].target,
+        PathBuf::from("missing")
+    );
+    assert!(!extras.symlinks[&base.join("dir_link")].broken);
+    assert!(!info.tree.contains_key(&base.join("dir_link")));
+    assert!(info.tree[&base]
+        .files
+        .iter()
+        .any(|f| f.path == base.join("dir_link")));
+    assert!(info.combined_size < 5000 + 4 * 1024);
+    assert!(info.tree[&base.join("data")]
+        .directories
+        .contains(&base.join("data/deep")));
+    assert_eq!(info.tree[&base].combined_size, info.combined_size);
+
+    // Following counts the targets, and stops at the loop
+    let (followed, extras) = scan::scan(&base, true, true, |_, _| {}, u128::MAX);
+    assert!(followed.tree.contains_key(&base.join("dir_link/deep")));
+    assert!(followed.combined_size >= 3 * 5000);
+    assert!(extras.symlinks[&base.join("broken")].broken);
+    assert!(extras.symlinks.contains_key(&base.join("data/loop")));
+    assert!(extras.errors.is_empty());
+
+    // What the links lead to is the same data again, not duplicates or more junk
+    let none = HashSet::new();
+    assert!(duplicate_dirs(&followed, &none, &extras.symlinks).is_empty());
+    let junk = find_junk(&followed, &none, &extras.symlinks);
+    assert_eq!(junk.len(), 1);
+    assert_eq!(junk[0].path, base.join("data/__pycache__"));
+    let (sender, receiver) = std::sync::mpsc::channel();
+    spawn_search(&followed, &extras.symlinks, sender);
+    loop {
+        if let DupeMessage::Done(groups) = receiver.recv().unwrap() {
+            assert!(groups.is_empty());
+            break;
+        }
+    }
+
+    let mut merged = ScanExtras::merge(vec![&extras]);
+    merged.remove(&base.join("data"));
+    assert!(!merged.symlinks.contains_key(&base.join("data/loop")));
+    assert!(merged.symlinks.contains_key(&base.join("broken")));
+
+    let _ = std::fs::remove_dir_all(&base);
+}
+
+#[cfg(unix)]
+#[test]
+fn scan_errors() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let base = std::env::temp_dir().join("birdseye_scan_errors");
+    let _ = std::fs::remove_dir_all(&base);
+    std::fs::create_dir_all(base.join("locked")).unwrap();
+    std::fs::write(base.join("locked/secret"), vec![0u8; 1000]).unwrap();
+    std::fs::write(base.join("open"), vec![0u8; 100]).unwrap();
+    // The size of this one is known, but it can't be hashed
+    std::fs::write(base.join("private"), vec![0u8; 10]).unwrap();
+    let locked = std::fs::Permissions::from_mode(0o000);
+    std::fs::set_permissions(base.join("locked"), locked.clone()).unwrap();
+    std::fs::set_permissions(base.join("private"), locked).unwrap();
+    // Root reads the directory anyway
+    let unreadable = std::fs::read_dir(base.join("locked")).is_err();
+
+    let (info, extras) = scan::scan(&base, false, true, |_, _| {}, u128::MAX);
+    if unreadable {
+        let mut paths: Vec<&PathBuf> = extras.errors.iter().map(|e| &e.path).collect();
+        paths.sort();
+        assert_eq!(paths, vec![&base.join("locked"), &base.join("private")]);
+        assert_eq!(
+            extras.errors_by_kind(),
+            vec![(std::io::ErrorKind::PermissionDenied, 2)]
+        );
+        assert_eq!(info.combined_size, 110);
+    } else {
+        assert!(extras.errors.is_empty());
+        assert_eq!(info.combined_size, 1110);
+    }
+
+    let mut merged = ScanExtras::merge(vec![&extras, &extras]);
+    assert_eq!(merged.errors.len(), 2 * extras.errors.len());
+    merged.remove(&base.join("locked"));
+    merged.remove(&base.join("private"));
+    assert!(merged.errors.is_empty());
+
+    let open = std::fs::Permissions::from_mode(0o755);
+    std::fs::set_permissions(base.join("locked"), open).unwrap();
+    let _ = std::fs::remove_dir_all(&base);
+}
+
+#[test]
+fn scan_helper() {
+    use std::cell::RefCell;
+    use std::ffi::OsString;
+
+    let base = std::env::temp_dir().join("birdseye_scan_helper");
+    let _ = std::fs::remove_dir_all(&base);
+    std::fs::create_dir_all(base.join("a")).unwrap();
+    std::fs::create_dir_all(base.join("b")).unwrap();
+    std::fs::write(base.join("a/x.bin"), vec![1u8; 700]).unwrap();
+    std::fs::write(base.join("b/x.bin"), vec![1u8; 700]).unwrap();
+
+    let mut out = vec![];
+    elevate::run_helper(&[OsString::from(&base)], &mut out).unwrap();
+    let mut reader = std::io::Cursor::new(out);
+    let (root, info, extras) = snapshot::read(&mut reader).unwrap().unwrap();
+    assert_eq!(root, base);
+    assert_eq!(info.combined_size, 1400);
+    assert!(extras.errors.is_empty());
+    assert!(snapshot::read(&mut reader).unwrap().is_none());
+    // The helper reads no contents, so nothing can be called a duplicate from its hashes
+    assert!(info.files.iter().all(|f| f.hash == 0));
+    assert!(duplicate_dirs(&info, &HashSet::new(), &HashMap::new()).is_empty());
+    let (hashed, _) = scan::scan(&base, false, true, |_, _| {}, u128::MAX);
+    assert_eq!(
+        duplicate_dirs(&hashed, &HashSet::new(), &HashMap::new()).len(),
+        1
+    );
+
+    // A script stands in for the helper: it checks its arguments and prints what
+    // the helper wrote for both directories. `env` stands in for pkexec.
+    #[cfg(unix)]
+    {
+        let dirs = vec![base.join("a"), base.join("b")];
+        let args: Vec<OsString> = dirs.iter().map(OsString::from).collect();
+        let mut out = vec![];
+        elevate::run_helper(&args, &mut out).unwrap();
+        std::fs::write(base.join("helper.out"), out).unwrap();
+        let script = base.join("helper.sh");
+        std::fs::write(
+            &script,
+            format!(
+                "[ \"$1\" = {} ] && [ \"$2\" = {} ] || exit 2\ncat {}\n",
+                elevate::HELPER_FLAG,
+                dirs[0].display(),
+                base.join("helper.out").display()
+            ),
+        )
+        .unwrap();
+
+        let scanned = RefCell::new(vec![]);
+        let mut command = Command::new("env");
+        command.arg("sh");
+        let count = elevate::rescan_through(command, &script, &dirs, false, &|dir, info, _| {
+            scanned.borrow_mut().push((dir, info.combined_size))
+        })
+        .unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(
+            scanned.into_inner(),
+            vec![(base.join("a"), 700), (base.join("b"), 700)]
+        );
+
+        // pkexec exits with 126 when the password dialog is dismissed
+        std::fs::write(&script, "exit 126\n").unwrap();
+        let error =
+            elevate::rescan_through(Command::new("sh"), &script, &dirs, false, &|_, _, _| {})
+                .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::PermissionDenied);
+    }
+
+    let _ = std::fs::remove_dir_all(&base);
+}
+
+#[test]
+fn snapshots() {
+    let base = std::env::temp_dir().join("birdseye_snapshots");
+    let _ = std::fs::remove_dir_all(&base);
+    // Tabs, newlines and backslashes must not break the lines
+    let odd = base.join("odd\tname\nwith\\slash");
+    std::fs::create_dir_all(odd.join("deep")).unwrap();
+    std::fs::write(odd.join("deep/data.bin"), vec![1u8; 3000]).unwrap();
+    std::fs::write(base.join("top.txt"), vec![2u8; 500]).unwrap();
+
+    let (info, mut extras) = scan::scan(&base, false, true, |_, _| {}, u128::MAX);
+    extras.errors.push(scan::ScanError {
+        path: base.join("locked"),
+        kind: std::io::ErrorKind::PermissionDenied,
+    });
+    let mut written = vec![];
+    snapshot::write(&mut written, &base, &info, &extras).unwrap();
+    snapshot::write(&mut written, &odd, &info, &ScanExtras::default()).unwrap();
+
+    let mut reader = std::io::Cursor::new(written);
+    let (root, read, read_extras) = snapshot::read(&mut reader).unwrap().unwrap();
+    assert_eq!(root, base);
+    assert_eq!(read.combined_size, 3500);
+    assert_eq!(read.tree[&odd].combined_size, 3000);
+    assert!(read.tree[&odd].directories.contains(&odd.join("deep")));
+    assert_eq!(read.files.len(), info.files.len());
+    let data = odd.join("deep/data.bin");
+    let file = read.files.iter().find(|f| f.path == data).unwrap();
+    let original = info.files.iter().find(|f| f.path == data).unwrap();
+    assert_eq!(file.hash, original.hash);
+    assert_eq!(file.ext, Some("bin".to_string()));
+    assert_eq!(read_extras.errors, extras.errors);
+    assert_eq!(read_extras.owners, extras.owners);
+    // Several snapshots follow each other on one stream
+    assert_eq!(snapshot::read(&mut reader).unwrap().unwrap().0, odd);
+    assert!(snapshot::read(&mut reader).unwrap().is_none());
+
+    let mut garbage = std::io::Cursor::new(b"not a snapshot\n".to_vec());
+    assert!(snapshot::read(&mut garbage).is_err());
+
+    let _ = std::fs::remove_dir_all(&base);
+}
+
+#[test]
+fn filling_in_rescans() {
+    let base = std::env::temp_dir().join("birdseye_filling_in");
+    let _ = std::fs::remove_dir_all(&base);
+    std::fs::create_dir_all(base.join("open")).unwrap();
+    std::fs::create_dir_all(base.join("locked")).unwrap();
+    std::fs::write(base.join("open/a.txt"), vec![0u8; 100]).unwrap();
+
+    // The first scan finds `locked` empty, as if it could not be read
+    let mut root = ScanRoot::new(&base.to_string_lossy());
+    let (info, mut extras) = scan::scan(&base, false, true, |_, _| {}, u128::MAX);
+    extras.errors.push(scan::ScanError {
+        path: base.join("locked"),
+        kind: std::io::ErrorKind::PermissionDenied,
+    });
+    root.info = info;
+    root.extras = extras;
+    assert_eq!(
+        elevate::denied_dirs(&root.info, &root.extras),
+        vec![base.join("locked")]
+    );
+
+    std::fs::create_dir_all(base.join("locked/deep")).unwrap();
+    std::fs::write(base.join("locked/deep/b.txt"), vec![0u8; 2000]).unwrap();
+    std::fs::write(base.join("locked/c.log"), vec![0u8; 30]).unwrap();
+    let (sub, sub_extras) = scan::scan(&base.join("locked"), false, true, |_, _| {}, u128::MAX);
+    fill_in(&mut root, &base.join("locked"), &sub, &sub_extras);
+
+    let (full, _) = scan::scan(&base, false, true, |_, _| {}, u128::MAX);
+    assert_eq!(root.info.combined_size, full.combined_size);
+    assert_eq!(root.info.files.len(), full.files.len());
+    for (path, dir) in &full.tree {
+        assert_eq!(root.info.tree[path].combined_size, dir.combined_size);
+    }
+    assert_eq!(root.info.filetypes["txt"].size, 2100);
+    assert!(root.extras.errors.is_empty());
+    assert!(elevate::denied_dirs(&root.info, &root.extras).is_empty());
+
+    // Items restored from the trash are scanned on their own and put back
+    std::fs::create_dir_all(base.join("back/deeper")).unwrap();
+    std::fs::write(base.join("back/deeper/d.txt"), vec![0u8; 500]).unwrap();
+    std::fs::write(base.join("open/e.txt"), vec![0u8; 7]).unwrap();
+    for path in &[base.join("back"), base.join("open/e.txt")] {
+        let (sub, sub_extras) = scan::scan(path, false, true, |_, _| {}, u128::MAX);
+        insert_scanned(&mut root, path, &sub, &sub_extras);
+    }
+    let (full, full_extras) = scan::scan(&base, false, true, |_, _| {}, u128::MAX);
+    assert_eq!(root.info.combined_size, full.combined_size);
+    assert_eq!(root.info.files.len(), full.files.len());
+    for (path, dir) in &full.tree {
+        assert_eq!(root.info.tree[path].combined_size, dir.combined_size);
+    }
+    assert_eq!(root.info.filetypes["txt"].size, 2607);
+    assert_eq!(root.extras.stats, full_extras.stats);
+
+    let _ = std::fs::remove_dir_all(&base);
+}
+
+#[cfg(unix)]
+#[test]
+fn owners() {
+    use std::os::unix::fs::MetadataExt;
+
+    let base = std::env::temp_dir().join("birdseye_owners");
+    let _ = std::fs::remove_dir_all(&base);
+    std::fs::create_dir_all(base.join("sub")).unwrap();
+    std::fs::write(base.join("small.txt"), vec![0u8; 10]).unwrap();
+    std::fs::write(base.join("sub/large.txt"), vec![0u8; 1000]).unwrap();
+    let meta = std::fs::metadata(base.join("small.txt")).unwrap();
+
+    let (mut info, extras) = scan::scan(&base, false, true, |_, _| {}, u128::MAX);
+    let owner = scan::Owner {
+        uid: meta.uid(),
+        gid: meta.gid(),
+    };
+    assert_eq!(extras.owners.get(&base.join("sub/large.txt")), Some(owner));
+    assert_eq!(extras.owners.get(&base.join("sub")), Some(owner));
+    // Files that belong to the owner of their directory are not stored on their own
+    assert_eq!(extras.owners.iter().count(), 2);
+    // Files inside archives have no owner of their own
+    info.files.push(File {
+        size: 5,
+        ext: None,
+        path: base.join("archive.zip/inner"),
+        modified: SystemTime::UNIX_EPOCH,
+        hash: 0,
+    });
+
+    let mut users = HashMap::new();
+    users.insert(meta.uid(), "someone".to_string());
+    let report = owners::owner_report(&info, &extras.owners, &users, &HashMap::new());
+    assert_eq!(report.users.len(), 1);
+    assert_eq!(report.users[0].name, "someone");
+    assert_eq!(report.users[0].size, 1010);
+    assert_eq!(report.users[0].files[0].path, base.join("sub/large.txt"));
+    assert_eq!(report.groups.len(), 1);
+    // Unknown groups are shown by their id
+    assert_eq!(report.groups[0].name, meta.gid().to_string());
+    assert_eq!(report.groups[0].files.len(), 2);
+    assert_eq!(report.unknown, 5);
+
+    let mut merged = ScanExtras::merge(vec![&extras]);
+    merged.remove(&base.join("sub"));
+    assert_eq!(merged.owners.iter().count(), 1);
+    assert_eq!(merged.owners.get(&base.join("sub/large.txt")), None);
+
+    let other = scan::Owner {
+        uid: 1234,
+        gid: 5678,
+    };
+    let mut owners = scan::Owners::default();
+    owners.add_dir(&base, owner);
+    owners.add_file(&base.join("mine"), owner);
+    owners.add_file(&base.join("theirs"), other);
+    assert_eq!(owners.iter().count(), 2);
+    assert_eq!(owners.get(&base.join("mine")), Some(owner));
+    assert_eq!(owners.get(&base.join("theirs")), Some(other));
+    assert_eq!(owners.get(&base.join("elsewhere/file")), None);
+
+    let _ = std::fs::remove_dir_all(&base);
+}
+
+#[test]
+fn tree_rows() {
+    use tree::{Row, PAGE_SIZE};
+    let base = std::env::temp_dir().join("birdseye_tree_rows");
+    let _ = std::fs::remove_dir_all(&base);
+    std::fs::create_dir_all(base.join("big")).unwrap();
+    std::fs::create_dir_all(base.join("many")).unwrap();
+    std::fs::write(base.join("big/blob.bin"), vec![0u8; 10000]).unwrap();
+    for i in 0..PAGE_SIZE + 50 {
+        std::fs::write(base.join(format!("many/{}.txt", i)), b"x").unwrap();
+    }
+    std::fs::write(base.join("top.txt"), vec![0u8; 5]).unwrap();
+
+    let (mut info, extras) = scan::scan(&base, false, true, |_, _| {}, u128::MAX);
+    let stats = extras.stats;
+    let archives = HashSet::new();
+    let top = [base.clone()];
+    let mut tree = DirTree::default();
+    let dir = |path: PathBuf, depth| Row::Dir { path, depth };
+    let top_file = Row::File {
+        parent: base.clone(),
+        index: 0,
+        depth: 1,
+    };
+
+    let rows = tree.build_rows(&info, &stats, &top, &archives);
+    assert_eq!(rows, vec![dir(base.clone(), 0)]);
+
+    // Subdirectories come first, largest first, then the files
+    tree.toggle(&base);
+    let collapsed = vec![
+        dir(base.clone(), 0),
+        dir(base.join("big"), 1),
+        dir(base.join("many"), 1),
+        top_file,
+    ];
+    assert_eq!(tree.build_rows(&info, &stats, &top, &archives), collapsed);
+
+    // Only a page of children is shown, the rest is behind a "more" row
+    tree.toggle(&base.join("many"));
+    let rows = tree.build_rows(&info, &stats, &top, &archives);
+    assert_eq!(rows.len(), 4 + PAGE_SIZE + 1);
+    assert_eq!(
+        rows[3 + PAGE_SIZE],
+        Row::More {
+            parent: base.join("many"),
+            remaining: 50,
+            depth: 2,
+        }
+    );
+    assert_eq!(rows.last(), collapsed.last());
+
+    tree.show_more(&base.join("many"));
+    let rows = tree.build_rows(&info, &stats, &top, &archives);
+    assert_eq!(rows.len(), 4 + PAGE_SIZE + 50);
+    assert!(!rows.iter().any(|r| matches!(r, Row::More { .. })));
+
+    tree.toggle(&base.join("many"));
+    assert_eq!(tree.build_rows(&info, &stats, &top, &archives), collapsed);
+
+    // After a deletion the cached children are dropped, expanded directories stay open
+    std::fs::remove_file(base.join("top.txt")).unwrap();
+    info.tree
+        .get_mut(&base)
+        .unwrap()
+        .files
+        .retain(|f| f.path != base.join("top.txt"));
+    info.files.retain(|f| f.path != base.join("top.txt"));
+    tree.invalidate();
+    let rows = tree.build_rows(&info, &stats, &top, &archives);
+    assert_eq!(rows, &collapsed[..3]);
+
+    let _ = std::fs::remove_dir_all(&base);
+}
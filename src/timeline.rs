@@ -0,0 +1,125 @@
+//! Bucketing files by modification date.
+
+use diskspace_insight::DirInfo;
+use eframe::egui::{Color32, Shape, Stroke, Ui};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub enum Granularity {
+    Month,
+    Year,
+}
+
+/// A calendar month or year
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Bucket {
+    pub year: i64,
+    /// 1 to 12, or 0 if bucketing by year
+    pub month: u32,
+}
+
+impl Bucket {
+    pub fn of(time: SystemTime, granularity: Granularity) -> Bucket {
+        let days = match time.duration_since(SystemTime::UNIX_EPOCH) {
+            Ok(d) => (d.as_secs() / 86400) as i64,
+            Err(e) => -((e.duration().as_secs() / 86400) as i64) - 1,
+        };
+        let (year, month, _) = civil_from_days(days);
+        match granularity {
+            Granularity::Month => Bucket { year, month },
+            Granularity::Year => Bucket { year, month: 0 },
+        }
+    }
+
+    pub fn label(&self) -> String {
+        if self.month == 0 {
+            format!("{}", self.year)
+        } else {
+            format!("{}-{:02}", self.year, self.month)
+        }
+    }
+}
+
+/// Convert days since 1970-01-01 to (year, month, day), after Howard Hinnant's `civil_from_days`
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = if z >= 0 { z } else { z - 146096 } / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Days since 1970-01-01 of a date, the inverse of `civil_from_days`
+pub fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let yoe = year - era * 400;
+    let month = month as i64;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Files modified within one bucket
+#[derive(Debug, Clone)]
+pub struct BucketStats {
+    pub bytes: u64,
+    pub count: u64,
+    /// Modification time of the newest file
+    pub newest: SystemTime,
+    /// Indices into `DirInfo::files`, largest first
+    pub files: Vec<usize>,
+}
+
+impl Default for BucketStats {
+    fn default() -> Self {
+        BucketStats {
+            bytes: 0,
+            count: 0,
+            newest: SystemTime::UNIX_EPOCH,
+            files: vec![],
+        }
+    }
+}
+
+/// Sort all files of a DirInfo into buckets, oldest first
+pub fn bucket_files(info: &DirInfo, granularity: Granularity) -> BTreeMap<Bucket, BucketStats> {
+    let mut buckets: BTreeMap<Bucket, BucketStats> = BTreeMap::new();
+    for (i, file) in info.files.iter().enumerate() {
+        let bucket = buckets
+            .entry(Bucket::of(file.modified, granularity))
+            .or_default();
+        bucket.bytes += file.size;
+        bucket.count += 1;
+        bucket.newest = bucket.newest.max(file.modified);
+        bucket.files.push(i);
+    }
+    for bucket in buckets.values_mut() {
+        bucket
+            .files
+            .sort_by_key(|i| std::cmp::Reverse(info.files[*i].size));
+    }
+    buckets
+}
+
+/// Like `paint_size_bar_before_next`, but a thin bar along the bottom of the row
+pub fn paint_count_bar_before_next(ui: &mut Ui, scale: f32, color: Color32) {
+    let mut paint_rect = ui.available_rect_before_wrap();
+    paint_rect.max.y = paint_rect.min.y + ui.style().spacing.interact_size.y + 2.;
+    paint_rect.min.y = paint_rect.max.y - 3.;
+    paint_rect.max.x = paint_rect.min.x + ui.available_rect_before_wrap().size().x * scale;
+
+    ui.painter().add(Shape::Rect {
+        rect: paint_rect,
+        corner_radius: 1.,
+        fill: color,
+        stroke: Stroke::default(),
+    });
+}
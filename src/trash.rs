@@ -0,0 +1,395 @@
+//! The freedesktop.org trash: moving files there, listing, restoring and purging them.
+
+#[cfg(unix)]
+use crate::fsinfo::mount_top;
+use crate::locations::disk_usage;
+use crate::timeline::civil_from_days;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::SystemTime;
+
+/// The trash in the user's home, `$XDG_DATA_HOME/Trash`
+pub fn home_trash() -> Option<PathBuf> {
+    std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|h| h.join(".local/share")))
+        .map(|d| d.join("Trash"))
+}
+
+/// Percent-encode a path for the `Path=` key of a .trashinfo file
+pub fn encode_path(path: &Path) -> String {
+    let mut encoded = String::new();
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// `YYYY-MM-DDThh:mm:ss`, in UTC
+fn deletion_date(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    let secs = secs % 86400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// `$topdir/.Trash/$uid`, if the administrator set up `$topdir/.Trash` as the spec asks:
+/// a real directory, not a symlink, with the sticky bit set
+#[cfg(unix)]
+fn shared_trash(top: &Path, uid: u32) -> Option<PathBuf> {
+    use std::os::unix::fs::PermissionsExt;
+    let shared = top.join(".Trash");
+    let meta = fs::symlink_metadata(&shared).ok()?;
+    if meta.is_dir() && meta.permissions().mode() & 0o1000 != 0 {
+        Some(shared.join(uid.to_string()))
+    } else {
+        None
+    }
+}
+
+/// The trash for `path`, and the top directory its original path is recorded relative to.
+/// Renaming only works within a filesystem, so paths that are not on the same one as the
+/// home trash go to a trash at the top of their own mount.
+#[cfg(unix)]
+fn trash_for(path: &Path) -> io::Result<(PathBuf, Option<PathBuf>)> {
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+    let home =
+        home_trash().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No home directory"))?;
+    let device = fs::symlink_metadata(path)?.dev();
+    // The home trash may not exist yet, what counts is where it would be created
+    let home_device = home
+        .ancestors()
+        .find_map(|a| fs::metadata(a).ok())
+        .map(|m| m.dev());
+    if home_device == Some(device) {
+        return Ok((home, None));
+    }
+
+    let top = mount_top(path)?;
+    let uid = unsafe { libc::getuid() };
+    let trash = match shared_trash(&top, uid) {
+        Some(trash) => trash,
+        None => top.join(format!(".Trash-{}", uid)),
+    };
+    if let Ok(meta) = fs::symlink_metadata(&trash) {
+        if !meta.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not a trash directory", trash.display()),
+            ));
+        }
+    } else {
+        fs::DirBuilder::new().mode(0o700).create(&trash)?;
+    }
+    Ok((trash, Some(top)))
+}
+
+#[cfg(not(unix))]
+fn trash_for(_path: &Path) -> io::Result<(PathBuf, Option<PathBuf>)> {
+    let home =
+        home_trash().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No home directory"))?;
+    Ok((home, None))
+}
+
+/// Move a file or directory into the trash of its filesystem
+pub fn move_to_trash(path: &Path) -> io::Result<()> {
+    // Resolve the parent only, a symlink is trashed itself and not its target
+    let path = match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) if !parent.as_os_str().is_empty() => {
+            fs::canonicalize(parent)?.join(name)
+        }
+        _ => fs::canonicalize(path)?,
+    };
+    let (trash, top) = trash_for(&path)?;
+    fs::create_dir_all(trash.join("files"))?;
+    fs::create_dir_all(trash.join("info"))?;
+    // Trashes at the top of a mount record paths relative to it
+    let recorded = match &top {
+        Some(top) => path.strip_prefix(top).unwrap_or(&path),
+        None => &path,
+    };
+
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    // Reserve a unique name by creating its info file exclusively
+    let mut i = 0;
+    loop {
+        let trashed_name = if i == 0 {
+            name.clone()
+        } else {
+            format!("{}.{}", name, i)
+        };
+        let info_path = trash
+            .join("info")
+            .join(format!("{}.trashinfo", trashed_name));
+        let mut info = match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&info_path)
+        {
+            Ok(f) => f,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                i += 1;
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        write!(
+            info,
+            "[Trash Info]\nPath={}\nDeletionDate={}\n",
+            encode_path(recorded),
+            deletion_date(SystemTime::now())
+        )?;
+        return fs::rename(&path, trash.join("files").join(&trashed_name)).inspect_err(|_| {
+            let _ = fs::remove_file(&info_path);
+        });
+    }
+}
+
+/// Decode the `%XX` escapes of a .trashinfo path
+pub fn decode_path(encoded: &str) -> String {
+    let bytes = encoded.as_bytes();
+    let mut decoded = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = if bytes[i] == b'%' {
+            encoded
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        } else {
+            None
+        };
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+/// The decoded `Path` and the `DeletionDate` of a .trashinfo file
+pub fn parse_trashinfo(content: &str) -> (Option<String>, Option<String>) {
+    let mut path = None;
+    let mut date = None;
+    for line in content.lines() {
+        if let Some(value) = line.strip_prefix("Path=") {
+            path = Some(decode_path(value.trim()));
+        } else if let Some(value) = line.strip_prefix("DeletionDate=") {
+            date = Some(value.trim().to_string());
+        }
+    }
+    (path, date)
+}
+
+/// Mount points, from /proc/self/mounts
+#[cfg(target_os = "linux")]
+fn mount_points() -> Vec<PathBuf> {
+    fs::read_to_string("/proc/self/mounts")
+        .map(|mounts| {
+            mounts
+                .lines()
+                .filter_map(|l| l.split_whitespace().nth(1))
+                // Spaces and other special characters are octal escaped
+                .map(|m| PathBuf::from(m.replace("\\040", " ").replace("\\011", "\t")))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn mount_points() -> Vec<PathBuf> {
+    vec![]
+}
+
+/// All trash directories of the current user: the home trash, and the
+/// `.Trash-$UID` or `.Trash/$UID` directories at the top of each mount
+pub fn trash_dirs() -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = home_trash().into_iter().collect();
+    #[cfg(unix)]
+    {
+        let uid = unsafe { libc::getuid() };
+        for mount in mount_points() {
+            dirs.push(mount.join(format!(".Trash-{}", uid)));
+            dirs.extend(shared_trash(&mount, uid));
+        }
+    }
+    dirs.retain(|d| d.join("files").is_dir());
+    dirs.sort();
+    dirs.dedup();
+    dirs
+}
+
+/// Something in a trash directory
+#[derive(Debug, Clone)]
+pub struct TrashItem {
+    /// The trashed file or directory itself, in the `files` directory of the trash
+    pub path: PathBuf,
+    /// Where it was deleted from, if its .trashinfo could be read
+    pub original: Option<PathBuf>,
+    pub deleted: Option<String>,
+    pub size: u64,
+}
+
+impl TrashItem {
+    fn info_path(&self) -> Option<PathBuf> {
+        let trash = self.path.parent()?.parent()?;
+        let name = self.path.file_name()?.to_string_lossy();
+        Some(trash.join("info").join(format!("{}.trashinfo", name)))
+    }
+}
+
+/// The items of one trash directory
+pub fn list_trash(trash: &Path) -> Vec<TrashItem> {
+    let entries = match fs::read_dir(trash.join("files")) {
+        Ok(entries) => entries,
+        Err(_) => return vec![],
+    };
+    let mut items: Vec<TrashItem> = entries
+        .flatten()
+        .map(|entry| {
+            let mut item = TrashItem {
+                path: entry.path(),
+                original: None,
+                deleted: None,
+                size: disk_usage(&entry.path()),
+            };
+            let info = item
+                .info_path()
+                .and_then(|p| fs::read_to_string(p).ok())
+                .unwrap_or_default();
+            let (original, deleted) = parse_trashinfo(&info);
+            // Paths in trashes on other mounts are relative to the top of the mount
+            let top = trash
+                .ancestors()
+                .find(|a| a.file_name().map(|n| n == ".Trash").unwrap_or_default())
+                .and_then(|a| a.parent())
+                .or_else(|| trash.parent());
+            item.original = original.map(|o| match top {
+                Some(top) => top.join(o),
+                None => PathBuf::from(o),
+            });
+            item.deleted = deleted;
+            item
+        })
+        .collect();
+    items.sort_by_key(|i| std::cmp::Reverse(i.size));
+    items
+}
+
+/// List all trash directories in the background
+pub fn spawn_list(sender: Sender<Vec<TrashItem>>) {
+    thread::spawn(move || {
+        let items = trash_dirs().iter().flat_map(|t| list_trash(t)).collect();
+        let _ = sender.send(items);
+    });
+}
+
+/// Move an item back to where it was deleted from. Existing files are not overwritten.
+pub fn restore(item: &TrashItem) -> io::Result<()> {
+    let original = item
+        .original
+        .as_ref()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Original path unknown"))?;
+    if fs::symlink_metadata(original).is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "Original path exists",
+        ));
+    }
+    if let Some(parent) = original.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::rename(&item.path, original)?;
+    if let Some(info) = item.info_path() {
+        let _ = fs::remove_file(info);
+    }
+    Ok(())
+}
+
+/// Delete an item for good
+pub fn purge(item: &TrashItem) -> io::Result<()> {
+    if fs::symlink_metadata(&item.path)?.is_dir() {
+        fs::remove_dir_all(&item.path)?;
+    } else {
+        fs::remove_file(&item.path)?;
+    }
+    if let Some(info) = item.info_path() {
+        let _ = fs::remove_file(info);
+    }
+    Ok(())
+}
+
+/// What to do with trashed items
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrashAction {
+    Restore,
+    Purge,
+}
+
+/// What the thread restoring or purging items reports
+#[derive(Debug, PartialEq)]
+pub enum TrashMessage {
+    /// Items handled so far, out of all of them
+    Progress {
+        done: usize,
+        total: usize,
+    },
+    /// An item left the trash. Restored items come with the path they are back at.
+    Handled {
+        path: PathBuf,
+        restored: Option<PathBuf>,
+    },
+    /// An item is still in the trash, with the reason
+    Failed(PathBuf, String),
+    Done,
+}
+
+/// Restore or purge items in the background, one after the other
+pub fn spawn_action(action: TrashAction, items: Vec<TrashItem>, sender: Sender<TrashMessage>) {
+    thread::spawn(move || {
+        let total = items.len();
+        for (done, item) in items.into_iter().enumerate() {
+            let _ = sender.send(TrashMessage::Progress { done, total });
+            let result = match action {
+                TrashAction::Restore => restore(&item).map(|()| item.original.clone()),
+                TrashAction::Purge => purge(&item).map(|()| None),
+            };
+            let msg = match result {
+                Ok(restored) => TrashMessage::Handled {
+                    path: item.path,
+                    restored,
+                },
+                Err(e) => TrashMessage::Failed(item.path, e.to_string()),
+            };
+            let _ = sender.send(msg);
+        }
+        let _ = sender.send(TrashMessage::Done);
+    });
+}
@@ -0,0 +1,380 @@
+//! The directory tree view. Sorted children are cached and only the visible rows are drawn,
+//! so that directories with lots of entries stay responsive.
+
+use crate::age::BarStyle;
+use crate::archive::{in_archive, in_nested_archive};
+use crate::cleanup::Removals;
+use crate::roots::Metric;
+use crate::scan::{DirStats, Symlink};
+use crate::table::format_age;
+use crate::{draw_file, draw_link_target, paint_size_bar_before_next};
+use bytesize::ByteSize;
+use diskspace_insight::{DirInfo, File};
+use eframe::egui::{ScrollArea, Ui};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+
+/// How many children of a directory are shown before a "more items" row
+pub(crate) const PAGE_SIZE: usize = 100;
+/// Horizontal indentation per tree level
+const INDENT: f32 = 12.;
+
+/// Children of a directory, largest first
+struct Children {
+    dirs: Vec<PathBuf>,
+    files: Vec<File>,
+}
+
+impl Children {
+    fn new(
+        dir: &Path,
+        info: &DirInfo,
+        stats: &HashMap<PathBuf, DirStats>,
+        metric: Metric,
+    ) -> Children {
+        match info.tree.get(dir) {
+            Some(d) => {
+                let mut dirs: Vec<PathBuf> =
+                    d.sorted_subdirs(info).into_iter().map(|d| d.path).collect();
+                if metric == Metric::Count {
+                    dirs.sort_by_key(|d| {
+                        std::cmp::Reverse(stats.get(d).map(|s| s.items()).unwrap_or_default())
+                    });
+                }
+                Children {
+                    dirs,
+                    files: d.sorted_files(),
+                }
+            }
+            None => Children {
+                dirs: vec![],
+                files: vec![],
+            },
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.dirs.len() + self.files.len()
+    }
+}
+
+/// One line in the tree
+#[derive(Debug, PartialEq)]
+pub(crate) enum Row {
+    Dir {
+        path: PathBuf,
+        depth: usize,
+    },
+    File {
+        parent: PathBuf,
+        index: usize,
+        depth: usize,
+    },
+    More {
+        parent: PathBuf,
+        remaining: usize,
+        depth: usize,
+    },
+}
+
+/// What the user asked for on a directory row
+#[derive(Debug, PartialEq)]
+pub enum TreeAction {
+    Zoom(PathBuf),
+    /// Pack the directory into an archive
+    Pack(PathBuf),
+    /// Move the directory elsewhere and leave a symlink
+    Relocate(PathBuf),
+}
+
+pub struct DirTree {
+    expanded: HashSet<PathBuf>,
+    /// How many children to show per directory, if more than a page
+    shown: HashMap<PathBuf, usize>,
+    children: HashMap<PathBuf, Children>,
+    rows: Option<Vec<Row>>,
+    /// The metric the cached children are sorted by
+    metric: Metric,
+    /// Directories deleted from the tree
+    removals: Removals,
+}
+
+impl Default for DirTree {
+    fn default() -> Self {
+        DirTree {
+            expanded: HashSet::new(),
+            shown: HashMap::new(),
+            children: HashMap::new(),
+            rows: None,
+            metric: Metric::Size,
+            removals: Removals::default(),
+        }
+    }
+}
+
+impl DirTree {
+    /// Drop cached children, e.g. after a scan or deletion. Expanded directories stay expanded.
+    pub fn invalidate(&mut self) {
+        self.children.clear();
+        self.rows = None;
+    }
+
+    pub(crate) fn toggle(&mut self, dir: &Path) {
+        if !self.expanded.remove(dir) {
+            self.expanded.insert(dir.to_path_buf());
+        }
+        self.rows = None;
+    }
+
+    pub(crate) fn show_more(&mut self, dir: &Path) {
+        *self.shown.entry(dir.to_path_buf()).or_insert(PAGE_SIZE) += PAGE_SIZE;
+        self.rows = None;
+    }
+
+    /// Flatten the expanded part of the tree into rows
+    pub(crate) fn build_rows(
+        &mut self,
+        info: &DirInfo,
+        stats: &HashMap<PathBuf, DirStats>,
+        top_dirs: &[PathBuf],
+        archives: &HashSet<PathBuf>,
+    ) -> Vec<Row> {
+        let mut rows = vec![];
+        for dir in top_dirs {
+            self.push_rows(info, stats, archives, dir, 0, &mut rows);
+        }
+        rows
+    }
+
+    fn push_rows(
+        &mut self,
+        info: &DirInfo,
+        stats: &HashMap<PathBuf, DirStats>,
+        archives: &HashSet<PathBuf>,
+        dir: &Path,
+        depth: usize,
+        rows: &mut Vec<Row>,
+    ) {
+        if !info.tree.contains_key(dir) {
+            return;
+        }
+        rows.push(Row::Dir {
+            path: dir.to_path_buf(),
+            depth,
+        });
+        if self.expanded.contains(dir) {
+            self.push_children(info, stats, archives, dir, depth + 1, rows);
+        }
+    }
+
+    fn push_children(
+        &mut self,
+        info: &DirInfo,
+        stats: &HashMap<PathBuf, DirStats>,
+        archives: &HashSet<PathBuf>,
+        dir: &Path,
+        depth: usize,
+        rows: &mut Vec<Row>,
+    ) {
+        let shown = self.shown.get(dir).copied().unwrap_or(PAGE_SIZE);
+        let metric = self.metric;
+        let children = self
+            .children
+            .entry(dir.to_path_buf())
+            .or_insert_with(|| Children::new(dir, info, stats, metric));
+        let subdirs: Vec<PathBuf> = children.dirs.iter().take(shown).cloned().collect();
+        let files: Vec<PathBuf> = children
+            .files
+            .iter()
+            .take(shown.saturating_sub(children.dirs.len()))
+            .map(|f| f.path.clone())
+            .collect();
+        let remaining = children.len().saturating_sub(shown);
+
+        for subdir in &subdirs {
+            self.push_rows(info, stats, archives, subdir, depth, rows);
+        }
+        for (index, file) in files.iter().enumerate() {
+            rows.push(Row::File {
+                parent: dir.to_path_buf(),
+                index,
+                depth,
+            });
+            // Archives with their contents in the tree open like directories
+            if archives.contains(file) && self.expanded.contains(file) {
+                self.push_children(info, stats, archives, file, depth + 1, rows);
+            }
+        }
+        if remaining > 0 {
+            rows.push(Row::More {
+                parent: dir.to_path_buf(),
+                remaining,
+                depth,
+            });
+        }
+    }
+
+    /// Draw the tree below `top_dirs`, with directories ranked by `metric`.
+    /// The contents of `archives` are shown, but can't be deleted or zoomed into.
+    /// Entries in `symlinks` are shown with their targets.
+    /// Returns what the user wants to do with a directory, if anything.
+    #[allow(clippy::too_many_arguments)]
+    pub fn show(
+        &mut self,
+        ui: &mut Ui,
+        info: &DirInfo,
+        stats: &HashMap<PathBuf, DirStats>,
+        metric: Metric,
+        top_dirs: &[PathBuf],
+        archives: &HashSet<PathBuf>,
+        symlinks: &HashMap<PathBuf, Symlink>,
+        allow_delete: bool,
+        bar_style: BarStyle,
+        del_sender: Sender<PathBuf>,
+        move_requests: Sender<PathBuf>,
+    ) -> Option<TreeAction> {
+        if self.metric != metric {
+            self.metric = metric;
+            self.invalidate();
+        }
+        if self.rows.is_none() {
+            self.rows = Some(self.build_rows(info, stats, top_dirs, archives));
+        }
+        self.removals.show(ui, &del_sender);
+        // Everything in the view uses an inode: the files and the directories
+        let total_items = (info.files.len() + info.tree.len()) as f32;
+
+        let mut toggled = None;
+        let mut more = None;
+        let mut action = None;
+        let rows = self.rows.as_deref().unwrap_or_default();
+        let children = &self.children;
+        let expanded = &self.expanded;
+        let removals = &mut self.removals;
+        let row_height = ui.spacing().interact_size.y;
+
+        ScrollArea::auto_sized().show_rows(ui, row_height, rows.len(), |ui, range| {
+            for row in &rows[range] {
+                match row {
+                    Row::Dir { path, depth } => {
+                        let dir = match info.tree.get(path) {
+                            Some(dir) => dir,
+                            None => continue,
+                        };
+                        let stat = stats.get(path).cloned().unwrap_or_default();
+                        let items = stat.items();
+                        let scale = match metric {
+                            Metric::Size => dir.combined_size as f32 / info.combined_size as f32,
+                            Metric::Count => items as f32 / total_items,
+                        };
+                        // Unpacked archive contents can be larger than everything on disk
+                        let scale = scale.min(1.);
+                        let virtual_dir = in_archive(path, archives);
+                        // Archives are files, even though their contents are shown like a tree
+                        let real_dir = !virtual_dir && !archives.contains(path);
+                        ui.horizontal(|ui| {
+                            ui.set_min_height(row_height);
+                            ui.add_space(*depth as f32 * INDENT);
+                            paint_size_bar_before_next(ui, scale, bar_style.color(stat.newest));
+                            let toggle = if expanded.contains(path) { "-" } else { "+" };
+                            if ui.button(toggle).clicked() {
+                                toggled = Some(path.clone());
+                            }
+                            ui.label(format!(
+                                "{} | {} | {} items | {}%",
+                                dir.path
+                                    .file_name()
+                                    .map(|d| d.to_string_lossy().to_string())
+                                    .unwrap_or_else(|| dir.path.to_string_lossy().to_string()),
+                                ByteSize(dir.combined_size),
+                                items,
+                                (scale * 100.) as u8
+                            ));
+                            if bar_style.by_age {
+                                ui.label(format!("newest {}", format_age(stat.newest)));
+                            }
+                            if virtual_dir {
+                                ui.label("in archive");
+                            }
+                            // A followed link to a directory
+                            if let Some(link) = symlinks.get(path) {
+                                draw_link_target(ui, link);
+                            }
+                            // Zooming needs the file list, which has no nested archive contents
+                            if !in_nested_archive(info, path, archives)
+                                && ui.button("Zoom").clicked()
+                            {
+                                action = Some(TreeAction::Zoom(path.clone()));
+                            }
+                            if real_dir && ui.button("Pack").clicked() {
+                                action = Some(TreeAction::Pack(path.clone()));
+                            }
+                            if allow_delete && real_dir && ui.button("Move to...").clicked() {
+                                let _ = move_requests.send(dir.path.to_path_buf());
+                            }
+                            if allow_delete && real_dir && ui.button("Relocate...").clicked() {
+                                action = Some(TreeAction::Relocate(path.clone()));
+                            }
+                            if allow_delete && real_dir && ui.button("Del").clicked() {
+                                removals.delete(&dir.path, true, &del_sender);
+                            }
+                        });
+                    }
+                    Row::File {
+                        parent,
+                        index,
+                        depth,
+                    } => {
+                        if let Some(file) = children.get(parent).and_then(|c| c.files.get(*index)) {
+                            ui.horizontal(|ui| {
+                                ui.set_min_height(row_height);
+                                ui.add_space(*depth as f32 * INDENT);
+                                if archives.contains(&file.path) {
+                                    let toggle = if expanded.contains(&file.path) {
+                                        "-"
+                                    } else {
+                                        "+"
+                                    };
+                                    if ui.button(toggle).clicked() {
+                                        toggled = Some(file.path.clone());
+                                    }
+                                }
+                                draw_file(
+                                    ui,
+                                    file,
+                                    symlinks.get(&file.path),
+                                    allow_delete,
+                                    archives,
+                                    del_sender.clone(),
+                                    move_requests.clone(),
+                                );
+                            });
+                        }
+                    }
+                    Row::More {
+                        parent,
+                        remaining,
+                        depth,
+                    } => {
+                        ui.horizontal(|ui| {
+                            ui.set_min_height(row_height);
+                            ui.add_space(*depth as f32 * INDENT);
+                            if ui.button(format!("{} more items...", remaining)).clicked() {
+                                more = Some(parent.clone());
+                            }
+                        });
+                    }
+                }
+            }
+        });
+
+        if let Some(dir) = toggled {
+            self.toggle(&dir);
+        }
+        if let Some(dir) = more {
+            self.show_more(&dir);
+        }
+        action
+    }
+}
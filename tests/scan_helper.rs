@@ -0,0 +1,31 @@
+//! The scan helper, started the way the app starts it with elevated privileges,
+//! only without the elevation.
+
+use std::process::Command;
+
+#[test]
+fn helper_process() {
+    let base = std::env::temp_dir().join("birdseye_helper_process");
+    let _ = std::fs::remove_dir_all(&base);
+    std::fs::create_dir_all(base.join("sub")).unwrap();
+    std::fs::write(base.join("sub/data.bin"), vec![1u8; 700]).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_birdseye"))
+        .arg("--scan-helper")
+        .arg(&base)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    // One snapshot block, for the directory that was asked for
+    let snapshot = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = snapshot.lines().collect();
+    assert_eq!(lines[0], "birdseye-snapshot 1");
+    assert_eq!(lines[1], format!("R\t{}", base.display()));
+    assert!(lines.contains(&format!("D\t{}", base.join("sub").display()).as_str()));
+    assert!(lines.iter().any(|l| l.starts_with("F\t700\t")
+        && l.ends_with(&format!("\t0\t{}", base.join("sub/data.bin").display()))));
+    assert_eq!(lines.last(), Some(&"."));
+
+    let _ = std::fs::remove_dir_all(&base);
+}